@@ -0,0 +1,747 @@
+//! etcd-rs, an etcdctl style command line client built on EtcdClient
+//!
+//! etcd-rs [global options] <command> [command options] [arguments]
+
+extern crate etcd_rs;
+extern crate getopts;
+extern crate rustc_serialize;
+
+use getopts::{Matches, Options, ParsingStyle};
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::process;
+use std::time::Duration;
+use etcd_rs::etcd::{EtcdClient, EtcdClientBuilder};
+use etcd_rs::etcd::backup::{ConflictPolicy, RestorePolicy};
+use etcd_rs::etcd::etcd_error::EtcdError;
+use etcd_rs::etcd::etcd_health::{ClusterStatus, MemberHealth};
+use etcd_rs::etcd::etcd_member::Member;
+use etcd_rs::etcd::etcd_node::EtcdNode;
+
+/// the members tried when --endpoints isn't given, the client and legacy client ports of a local etcd
+static DEFAULT_ENDPOINTS: &'static str = "http://127.0.0.1:2379,http://127.0.0.1:4001";
+
+static COMMANDS: &'static str = "Commands:
+    get <key>                      print the value of a key
+    set <key> <value>              set the value of a key
+    mk <key> <value>               create a key, fails if it exists
+    mkdir <dir>                    create a directory, fails if it exists
+    rm <key>                       remove a key, or a directory with --dir or --recursive
+    rmdir <dir>                    remove an empty directory
+    ls [dir]                       list the keys of a directory
+    update <key> <value>           update the value of an existing key
+    watch <key>                    print the next change to a key, or every change with --forever
+    exec-watch <key> -- <command>  run the command on every change to a key
+    cluster-health                 check the health of each member of the cluster
+    member list                    list the members of the cluster
+    member add <peer url>...       add a member with the peer urls
+    member remove <id>             remove the member
+    backup <dir>                   write the keys of a directory to a backup file
+    restore <dir>                  restore a backup file into a directory
+
+See etcd-rs <command> --help for the options of each command.";
+
+/// how the results are printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Output {
+    /// the values, keys or messages, one per line
+    Simple,
+    /// a json object per result, one per line
+    Json,
+}
+
+impl Output {
+    fn parse(output: &str) -> Result<Output, CliError> {
+        return match output {
+            "simple" => Ok(Output::Simple),
+            "json" => Ok(Output::Json),
+            _ => Err(CliError::Usage(format!("unknown output format {}, expected simple or json", output))),
+        };
+    }
+}
+
+#[derive(Debug)]
+enum CliError {
+    /// the --help of a command was asked for, it's printed rather than treated as an error
+    Help(String),
+    /// the arguments were not understood
+    Usage(String),
+    /// the command failed, i.e. the cluster is unhealthy
+    Failed(String),
+    Etcd(EtcdError),
+    IOError(io::Error),
+}
+
+impl From<EtcdError> for CliError {
+    fn from(err: EtcdError) -> CliError {
+        return CliError::Etcd(err);
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> CliError {
+        return CliError::IOError(err);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let code = match run(&args) {
+        Ok(()) => 0,
+        Err(CliError::Help(usage)) => {
+            println!("{}", usage);
+            0
+        },
+        Err(CliError::Usage(message)) => {
+            let _ = writeln!(io::stderr(), "{}", message);
+            2
+        },
+        Err(CliError::Failed(message)) => {
+            let _ = writeln!(io::stderr(), "{}", message);
+            1
+        },
+        Err(CliError::Etcd(err)) => {
+            let _ = writeln!(io::stderr(), "Error: {}", err);
+            1
+        },
+        Err(CliError::IOError(err)) => {
+            let _ = writeln!(io::stderr(), "Error: {}", err);
+            1
+        },
+    };
+
+    process::exit(code);
+}
+
+fn global_options() -> Options {
+    let mut opts = Options::new();
+
+    // the options after the command are those of the command
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    opts.optopt("", "endpoints", &format!("comma separated urls of the members, defaults to {}", DEFAULT_ENDPOINTS), "URLS");
+    opts.optopt("", "ca-file", "the PEM bundle of CAs which sign the member certificates", "FILE");
+    opts.optopt("", "cert-file", "the PEM certificate to authenticate with, requires --key-file", "FILE");
+    opts.optopt("", "key-file", "the PEM private key of --cert-file", "FILE");
+    opts.optopt("o", "output", "simple or json, defaults to simple", "FORMAT");
+    opts.optflag("h", "help", "print this help");
+
+    return opts;
+}
+
+fn run(args: &[String]) -> Result<(), CliError> {
+    let opts = global_options();
+    let brief = "usage: etcd-rs [options] <command> [command options] [arguments]";
+
+    let matches = try!(opts.parse(args).map_err(|e| CliError::Usage(format!("{}\n\n{}", e, opts.usage(brief)))));
+    if matches.opt_present("help") {
+        return Err(CliError::Help(format!("{}\n\n{}", opts.usage(brief), COMMANDS)));
+    }
+
+    if matches.free.is_empty() {
+        return Err(CliError::Usage(format!("{}\n\n{}", opts.usage(brief), COMMANDS)));
+    }
+
+    let output = try!(Output::parse(&matches.opt_str("output").unwrap_or("simple".to_string())));
+    let cli = Cli{ client: try!(build_client(&matches)), output: output };
+
+    let command = &matches.free[0];
+    let args = &matches.free[1..];
+
+    return match command as &str {
+        "get" => cli.get(args),
+        "set" => cli.set(args),
+        "mk" => cli.mk(args),
+        "mkdir" => cli.mkdir(args),
+        "rm" => cli.rm(args),
+        "rmdir" => cli.rmdir(args),
+        "ls" => cli.ls(args),
+        "update" => cli.update(args),
+        "watch" => cli.watch(args),
+        "exec-watch" => cli.exec_watch(args),
+        "cluster-health" => cli.cluster_health(args),
+        "member" => cli.member(args),
+        "backup" => cli.backup(args),
+        "restore" => cli.restore(args),
+        _ => Err(CliError::Usage(format!("unknown command {}\n\n{}", command, COMMANDS))),
+    };
+}
+
+fn build_client(matches: &Matches) -> Result<EtcdClient, CliError> {
+    let endpoints = matches.opt_str("endpoints").unwrap_or(DEFAULT_ENDPOINTS.to_string());
+
+    let mut builder = EtcdClientBuilder::new().endpoints(&split_endpoints(&endpoints));
+    if let Some(ca_file) = matches.opt_str("ca-file") {
+        builder = builder.ca_file(ca_file);
+    }
+
+    builder = match (matches.opt_str("cert-file"), matches.opt_str("key-file")) {
+        (Some(cert_file), Some(key_file)) => builder.client_cert(cert_file, key_file),
+        (None, None) => builder,
+        _ => return Err(CliError::Usage("--cert-file and --key-file must be given together".to_string())),
+    };
+
+    return Ok(try!(builder.build()));
+}
+
+/// the urls of a comma separated list, ignoring any spaces around them
+fn split_endpoints(endpoints: &str) -> Vec<&str> {
+    return endpoints.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()).collect();
+}
+
+/// parses the options of the command, between min and max arguments must follow them
+fn parse_command(mut opts: Options, args: &[String], usage: &str, min: usize, max: usize) -> Result<Matches, CliError> {
+    opts.optflag("h", "help", "print this help");
+    let brief = format!("usage: etcd-rs {}", usage);
+
+    let matches = try!(opts.parse(args).map_err(|e| CliError::Usage(format!("{}\n\n{}", e, opts.usage(&brief)))));
+    if matches.opt_present("help") {
+        return Err(CliError::Help(opts.usage(&brief)));
+    }
+
+    if matches.free.len() < min || matches.free.len() > max {
+        return Err(CliError::Usage(opts.usage(&brief)));
+    }
+
+    return Ok(matches);
+}
+
+/// the value of an option which must be a number
+fn opt_u64(matches: &Matches, name: &str) -> Result<Option<u64>, CliError> {
+    return match matches.opt_str(name) {
+        Some(value) => value.parse::<u64>().map(Some).map_err(|_| CliError::Usage(format!("--{} must be a number: {}", name, value))),
+        None => Ok(None),
+    };
+}
+
+fn ttl_options() -> Options {
+    let mut opts = Options::new();
+    opts.optopt("", "ttl", "remove the key after this many seconds", "SECONDS");
+
+    return opts;
+}
+
+/// parses --conflict skip|overwrite|fail
+fn parse_conflict(conflict: &str) -> Result<ConflictPolicy, CliError> {
+    return match conflict {
+        "skip" => Ok(ConflictPolicy::Skip),
+        "overwrite" => Ok(ConflictPolicy::Overwrite),
+        "fail" => Ok(ConflictPolicy::Fail),
+        _ => Err(CliError::Usage(format!("unknown conflict policy {}, expected skip, overwrite or fail", conflict))),
+    };
+}
+
+/// parses --rewrite FROM=TO
+fn parse_rewrite(rewrite: &str) -> Result<(&str, &str), CliError> {
+    return match rewrite.find('=') {
+        Some(i) => Ok((&rewrite[..i], &rewrite[i + 1..])),
+        None => Err(CliError::Usage(format!("--rewrite must be FROM=TO: {}", rewrite))),
+    };
+}
+
+struct Cli {
+    client: EtcdClient,
+    output: Output,
+}
+
+impl Cli {
+    /// prints the json on a line of its own
+    fn print_json(&self, json: Json) {
+        println!("{}", json);
+    }
+
+    fn get(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(Options::new(), args, "get <key>", 1, 1));
+        let key = &matches.free[0];
+        let result = try!(self.client.get_result(key, false, false));
+
+        if self.output == Output::Json {
+            self.print_json(result_json(&result.action, result.node.as_ref(), result.previous_node.as_ref()));
+            return Ok(());
+        }
+
+        return match result.node {
+            Some(ref node) if node.dir => Err(CliError::Failed(format!("{}: is a directory", node.key))),
+            Some(ref node) => {
+                println!("{}", node.value.as_ref().map(|v| v as &str).unwrap_or(""));
+                Ok(())
+            },
+            None => Ok(()),
+        };
+    }
+
+    fn set(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(ttl_options(), args, "set [--ttl SECONDS] <key> <value>", 2, 2));
+        let previous = try!(self.client.set(&matches.free[0], &matches.free[1], try!(opt_u64(&matches, "ttl"))));
+
+        return self.print_write("set", &matches.free[1], None, previous.as_ref());
+    }
+
+    fn mk(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(ttl_options(), args, "mk [--ttl SECONDS] <key> <value>", 2, 2));
+        let node = try!(self.client.create(&matches.free[0], &matches.free[1], try!(opt_u64(&matches, "ttl"))));
+
+        return self.print_write("create", &matches.free[1], node.as_ref(), None);
+    }
+
+    fn update(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(ttl_options(), args, "update [--ttl SECONDS] <key> <value>", 2, 2));
+        let previous = try!(self.client.update(&matches.free[0], &matches.free[1], try!(opt_u64(&matches, "ttl"))));
+
+        return self.print_write("update", &matches.free[1], None, previous.as_ref());
+    }
+
+    /// the value written, or the nodes the client returned for it
+    fn print_write(&self, action: &str, value: &str, node: Option<&EtcdNode>, previous: Option<&EtcdNode>) -> Result<(), CliError> {
+        match self.output {
+            Output::Simple => println!("{}", value),
+            Output::Json => self.print_json(result_json(action, node, previous)),
+        }
+
+        return Ok(());
+    }
+
+    fn mkdir(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(ttl_options(), args, "mkdir [--ttl SECONDS] <dir>", 1, 1));
+        let node = try!(self.client.create_dir(&matches.free[0], try!(opt_u64(&matches, "ttl"))));
+
+        if self.output == Output::Json {
+            self.print_json(result_json("create", node.as_ref(), None));
+        }
+
+        return Ok(());
+    }
+
+    fn rm(&self, args: &[String]) -> Result<(), CliError> {
+        let mut opts = Options::new();
+        opts.optflag("", "dir", "remove the key if it's an empty directory or a key");
+        opts.optflag("r", "recursive", "remove the key and everything beneath it");
+
+        let matches = try!(parse_command(opts, args, "rm [--dir] [--recursive] <key>", 1, 1));
+        let key = &matches.free[0];
+
+        let previous = if matches.opt_present("recursive") {
+            try!(self.client.remove_dir(key, true))
+        } else if matches.opt_present("dir") {
+            try!(self.client.remove_dir(key, false))
+        } else {
+            try!(self.client.remove(key))
+        };
+
+        return self.print_remove(previous.as_ref());
+    }
+
+    fn rmdir(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(Options::new(), args, "rmdir <dir>", 1, 1));
+        let previous = try!(self.client.remove_dir(&matches.free[0], false));
+
+        return self.print_remove(previous.as_ref());
+    }
+
+    /// nothing, or the node which was removed
+    fn print_remove(&self, previous: Option<&EtcdNode>) -> Result<(), CliError> {
+        if self.output == Output::Json {
+            self.print_json(result_json("delete", None, previous));
+        }
+
+        return Ok(());
+    }
+
+    fn ls(&self, args: &[String]) -> Result<(), CliError> {
+        let mut opts = Options::new();
+        opts.optflag("r", "recursive", "list the keys of the directories beneath it too");
+        opts.optflag("s", "sort", "list the keys in order");
+
+        let matches = try!(parse_command(opts, args, "ls [--recursive] [--sort] [dir]", 0, 1));
+        let dir = matches.free.get(0).map(|d| d as &str).unwrap_or("/");
+        let result = try!(self.client.get_result(dir, matches.opt_present("recursive"), matches.opt_present("sort")));
+
+        if self.output == Output::Json {
+            self.print_json(result_json(&result.action, result.node.as_ref(), None));
+            return Ok(());
+        }
+
+        if let Some(ref node) = result.node {
+            if node.dir {
+                print_keys(node);
+            } else {
+                println!("{}", node.key);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn watch(&self, args: &[String]) -> Result<(), CliError> {
+        let mut opts = Options::new();
+        opts.optflag("f", "forever", "print every change until interrupted, rather than only the next");
+        opts.optflag("r", "recursive", "watch the keys beneath the directory");
+        opts.optopt("i", "after-index", "print the changes after this index, rather than from now", "INDEX");
+
+        let matches = try!(parse_command(opts, args, "watch [--forever] [--recursive] [--after-index INDEX] <key>", 1, 1));
+        let wait_index = try!(opt_u64(&matches, "after-index")).map(|index| index + 1);
+
+        for event in self.client.watcher(&matches.free[0], wait_index, matches.opt_present("recursive")) {
+            let result = try!(event);
+
+            match self.output {
+                Output::Simple => {
+                    println!("[{}] {}", result.action, result.node.as_ref().map(|n| &n.key as &str).unwrap_or(""));
+                    if let Some(value) = result.node.as_ref().and_then(|n| n.value.as_ref()) {
+                        println!("{}", value);
+                    }
+                },
+                Output::Json => self.print_json(result_json(&result.action, result.node.as_ref(), result.previous_node.as_ref())),
+            }
+
+            if !matches.opt_present("forever") {
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn exec_watch(&self, args: &[String]) -> Result<(), CliError> {
+        let mut opts = Options::new();
+        opts.optflag("r", "recursive", "watch the keys beneath the directory");
+        opts.optopt("i", "after-index", "run the command on the changes after this index, rather than from now", "INDEX");
+
+        let usage = "exec-watch [--recursive] [--after-index INDEX] <key> -- <command> [arguments]";
+        let matches = try!(parse_command(opts, args, usage, 2, usize::max_value()));
+        let wait_index = try!(opt_u64(&matches, "after-index")).map(|index| index + 1);
+        let command: Vec<&str> = matches.free[1..].iter().map(|a| a as &str).collect();
+
+        try!(self.client.watcher(&matches.free[0], wait_index, matches.opt_present("recursive")).exec(&command));
+        return Ok(());
+    }
+
+    fn cluster_health(&self, args: &[String]) -> Result<(), CliError> {
+        try!(parse_command(Options::new(), args, "cluster-health", 0, 0));
+        let health = try!(self.client.cluster_health());
+
+        let status = match health.status {
+            ClusterStatus::Healthy => "healthy",
+            ClusterStatus::Degraded => "degraded",
+            ClusterStatus::Unhealthy => "unhealthy",
+        };
+
+        match self.output {
+            Output::Simple => {
+                for member in &health.members {
+                    println!("{}", describe_health(member));
+                }
+
+                println!("cluster is {}", status);
+            },
+            Output::Json => {
+                let mut obj = BTreeMap::new();
+                obj.insert("members".to_string(), Json::Array(health.members.iter().map(health_json).collect()));
+                obj.insert("status".to_string(), Json::String(status.to_string()));
+
+                self.print_json(Json::Object(obj));
+            },
+        }
+
+        // the cluster can still accept writes while it's degraded
+        if health.status == ClusterStatus::Unhealthy {
+            return Err(CliError::Failed("cluster is unhealthy".to_string()));
+        }
+
+        return Ok(());
+    }
+
+    fn member(&self, args: &[String]) -> Result<(), CliError> {
+        let usage = "usage: etcd-rs member <list | add <peer url>... | remove <id>>";
+
+        return match args.get(0).map(|a| a as &str) {
+            Some("list") => self.member_list(&args[1..]),
+            Some("add") => self.member_add(&args[1..]),
+            Some("remove") => self.member_remove(&args[1..]),
+            Some("-h") | Some("--help") => Err(CliError::Help(usage.to_string())),
+            _ => Err(CliError::Usage(usage.to_string())),
+        };
+    }
+
+    fn member_list(&self, args: &[String]) -> Result<(), CliError> {
+        try!(parse_command(Options::new(), args, "member list", 0, 0));
+        let members = try!(self.client.members_list());
+
+        match self.output {
+            Output::Simple => {
+                for member in &members {
+                    println!("{}: name={} peerURLs={} clientURLs={}", member.id, member.name, member.peer_urls.join(","),
+                             member.client_urls.join(","));
+                }
+            },
+            Output::Json => {
+                let mut obj = BTreeMap::new();
+                obj.insert("members".to_string(), Json::Array(members.iter().map(member_json).collect()));
+
+                self.print_json(Json::Object(obj));
+            },
+        }
+
+        return Ok(());
+    }
+
+    fn member_add(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(Options::new(), args, "member add <peer url>...", 1, usize::max_value()));
+        let peer_urls: Vec<&str> = matches.free.iter().map(|u| u as &str).collect();
+        let member = try!(self.client.member_add(&peer_urls));
+
+        match self.output {
+            Output::Simple => println!("Added member {} with peerURLs {}", member.id, member.peer_urls.join(",")),
+            Output::Json => self.print_json(member_json(&member)),
+        }
+
+        return Ok(());
+    }
+
+    fn member_remove(&self, args: &[String]) -> Result<(), CliError> {
+        let matches = try!(parse_command(Options::new(), args, "member remove <id>", 1, 1));
+        try!(self.client.member_remove(&matches.free[0]));
+
+        if self.output == Output::Simple {
+            println!("Removed member {} from the cluster", matches.free[0]);
+        }
+
+        return Ok(());
+    }
+
+    fn backup(&self, args: &[String]) -> Result<(), CliError> {
+        let mut opts = Options::new();
+        opts.optopt("f", "file", "write the backup to the file rather than stdout", "FILE");
+
+        let matches = try!(parse_command(opts, args, "backup [--file FILE] <dir>", 1, 1));
+        let dir = &matches.free[0];
+
+        let summary = match matches.opt_str("file") {
+            Some(file) => try!(self.client.backup(dir, &mut try!(File::create(file)))),
+            None => {
+                // the backup is the output, there's nothing more to print
+                let stdout = io::stdout();
+                try!(self.client.backup(dir, &mut stdout.lock()));
+                return Ok(());
+            },
+        };
+
+        match self.output {
+            Output::Simple => println!("backed up {} keys and {} directories of {} at index {}", summary.keys, summary.dirs, dir, summary.index),
+            Output::Json => {
+                let mut obj = BTreeMap::new();
+                obj.insert("index".to_string(), Json::U64(summary.index));
+                obj.insert("keys".to_string(), Json::U64(summary.keys as u64));
+                obj.insert("dirs".to_string(), Json::U64(summary.dirs as u64));
+
+                self.print_json(Json::Object(obj));
+            },
+        }
+
+        return Ok(());
+    }
+
+    fn restore(&self, args: &[String]) -> Result<(), CliError> {
+        let mut opts = Options::new();
+        opts.optopt("f", "file", "read the backup from the file rather than stdin", "FILE");
+        opts.optopt("", "conflict", "skip, overwrite or fail on the keys which exist, defaults to fail", "POLICY");
+        opts.optmulti("", "rewrite", "restore the keys beginning with FROM as beginning with TO", "FROM=TO");
+
+        let matches = try!(parse_command(opts, args, "restore [--file FILE] [--conflict POLICY] [--rewrite FROM=TO]... <dir>", 1, 1));
+
+        let mut policy = RestorePolicy::new(try!(parse_conflict(&matches.opt_str("conflict").unwrap_or("fail".to_string()))));
+        for rewrite in matches.opt_strs("rewrite") {
+            let (from, to) = try!(parse_rewrite(&rewrite));
+            policy = policy.rewrite(from, to);
+        }
+
+        let dir = &matches.free[0];
+        let summary = match matches.opt_str("file") {
+            Some(file) => try!(self.client.restore(&mut try!(File::open(file)), dir, &policy)),
+            None => {
+                // the lock must be released before stdin is
+                let stdin = io::stdin();
+                let summary = try!(self.client.restore(&mut stdin.lock(), dir, &policy));
+                summary
+            },
+        };
+
+        match self.output {
+            Output::Simple => {
+                println!("restored {} keys to {}, {} were overwritten and {} skipped", summary.created, dir, summary.overwritten,
+                         summary.skipped);
+            },
+            Output::Json => {
+                let mut obj = BTreeMap::new();
+                obj.insert("created".to_string(), Json::U64(summary.created as u64));
+                obj.insert("overwritten".to_string(), Json::U64(summary.overwritten as u64));
+                obj.insert("skipped".to_string(), Json::U64(summary.skipped as u64));
+
+                self.print_json(Json::Object(obj));
+            },
+        }
+
+        return Ok(());
+    }
+}
+
+/// prints the keys in the directory, and those of any directories listed beneath it
+fn print_keys(dir: &EtcdNode) {
+    for node in dir.nodes.iter().flat_map(|n| n.iter()) {
+        println!("{}", node.key);
+        print_keys(node);
+    }
+}
+
+/// the health as etcdctl prints it, i.e. member ce2a822cea30bfca is healthy: got healthy result from http://..
+fn describe_health(health: &MemberHealth) -> String {
+    let member = &health.member;
+    let url = member.client_urls.get(0).map(|u| u as &str).unwrap_or("");
+
+    if !health.reachable {
+        let error = health.error.as_ref().map(|e| e.to_string()).unwrap_or("no client urls".to_string());
+        return format!("member {} is unreachable: {}", member.id, error);
+    }
+
+    let verdict = if health.healthy { "healthy" } else { "unhealthy" };
+    let leader = if health.leader { " (leader)" } else { "" };
+    let failed = match (health.failed_check, health.error.as_ref()) {
+        (Some(check), Some(error)) => format!(", but {} failed: {}", check, error),
+        _ => String::new(),
+    };
+
+    return format!("member {} is {}: got {} result from {}{}{}", member.id, verdict, verdict, url, leader, failed);
+}
+
+/// {"action":..,"node":..,"prevNode":..} as etcd answers, the nodes the client didn't return are left out
+fn result_json(action: &str, node: Option<&EtcdNode>, previous: Option<&EtcdNode>) -> Json {
+    let mut obj = BTreeMap::new();
+    obj.insert("action".to_string(), Json::String(action.to_string()));
+
+    if let Some(node) = node {
+        obj.insert("node".to_string(), node_json(node));
+    }
+
+    if let Some(previous) = previous {
+        obj.insert("prevNode".to_string(), node_json(previous));
+    }
+
+    return Json::Object(obj);
+}
+
+/// the node in the json of etcd, i.e. {"key":..,"value":..,"createdIndex":..,"modifiedIndex":..}
+fn node_json(node: &EtcdNode) -> Json {
+    let mut obj = BTreeMap::new();
+    obj.insert("key".to_string(), Json::String(node.key.clone()));
+    obj.insert("createdIndex".to_string(), Json::I64(node.created_index));
+    obj.insert("modifiedIndex".to_string(), Json::I64(node.modified_index));
+
+    if let Some(ref value) = node.value {
+        obj.insert("value".to_string(), Json::String(value.clone()));
+    }
+
+    if node.dir {
+        obj.insert("dir".to_string(), Json::Boolean(true));
+    }
+
+    if let Some(ttl) = node.ttl {
+        obj.insert("ttl".to_string(), Json::I64(ttl));
+    }
+
+    if let Some(ref expiration) = node.expiration {
+        obj.insert("expiration".to_string(), Json::String(expiration.clone()));
+    }
+
+    if let Some(ref nodes) = node.nodes {
+        obj.insert("nodes".to_string(), Json::Array(nodes.iter().map(node_json).collect()));
+    }
+
+    return Json::Object(obj);
+}
+
+/// {"id":..,"name":..,"peerURLs":[..],"clientURLs":[..]}
+fn member_json(member: &Member) -> Json {
+    let mut obj = BTreeMap::new();
+    obj.insert("id".to_string(), Json::String(member.id.clone()));
+    obj.insert("name".to_string(), Json::String(member.name.clone()));
+    obj.insert("peerURLs".to_string(), Json::Array(member.peer_urls.iter().map(|u| Json::String(u.clone())).collect()));
+    obj.insert("clientURLs".to_string(), Json::Array(member.client_urls.iter().map(|u| Json::String(u.clone())).collect()));
+
+    return Json::Object(obj);
+}
+
+/// the member along with its health, the latency is in milliseconds
+fn health_json(health: &MemberHealth) -> Json {
+    let mut obj = match member_json(&health.member) {
+        Json::Object(obj) => obj,
+        _ => BTreeMap::new(),
+    };
+
+    obj.insert("reachable".to_string(), Json::Boolean(health.reachable));
+    obj.insert("healthy".to_string(), Json::Boolean(health.healthy));
+    obj.insert("leader".to_string(), Json::Boolean(health.leader));
+
+    if let Some(raft_term) = health.raft_term {
+        obj.insert("raftTerm".to_string(), Json::U64(raft_term));
+    }
+
+    if let Some(latency) = health.latency {
+        obj.insert("latency".to_string(), Json::U64(millis(latency)));
+    }
+
+    if let Some(ref error) = health.error {
+        obj.insert("error".to_string(), Json::String(error.to_string()));
+    }
+
+    if let Some(check) = health.failed_check {
+        obj.insert("failedCheck".to_string(), Json::String(check.to_string()));
+    }
+
+    return Json::Object(obj);
+}
+
+fn millis(duration: Duration) -> u64 {
+    return duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{parse_command, parse_conflict, parse_rewrite, split_endpoints, ttl_options, CliError, Output};
+  use etcd_rs::etcd::backup::ConflictPolicy;
+
+  fn args(args: &[&str]) -> Vec<String> {
+    return args.iter().map(|a| a.to_string()).collect();
+  }
+
+  #[test]
+  fn parse_options_test() {
+    assert_eq!(split_endpoints("http://10.0.0.1:2379, http://10.0.0.2:2379,"), vec!["http://10.0.0.1:2379", "http://10.0.0.2:2379"]);
+    assert_eq!(Output::parse("json").unwrap(), Output::Json);
+    assert!(Output::parse("yaml").is_err());
+
+    assert_eq!(parse_conflict("overwrite").unwrap(), ConflictPolicy::Overwrite);
+    assert_eq!(parse_rewrite("jobs/=archived/jobs/").unwrap(), ("jobs/", "archived/jobs/"));
+    assert!(parse_rewrite("jobs/").is_err());
+  }
+
+  #[test]
+  fn parse_command_test() {
+    let matches = parse_command(ttl_options(), &args(&["--ttl", "5", "/foo", "bar"]), "set", 2, 2).unwrap();
+    assert_eq!(matches.opt_str("ttl"), Some("5".to_string()));
+    assert_eq!(matches.free, args(&["/foo", "bar"]));
+
+    match parse_command(ttl_options(), &args(&["/foo"]), "set", 2, 2) {
+      Err(CliError::Usage(..)) => (),
+      r => panic!("expected Usage: {:?}", r.map(|m| m.free)),
+    }
+
+    match parse_command(ttl_options(), &args(&["--help"]), "set", 2, 2) {
+      Err(CliError::Help(..)) => (),
+      r => panic!("expected Help: {:?}", r.map(|m| m.free)),
+    }
+  }
+}
@@ -0,0 +1,358 @@
+//! Backup of a directory to a portable json file, and its restore to the same or another cluster.
+//!
+//! The file is {"format":"etcd-rs-backup","version":1,"dir":..,"index":..,"nodes":[..]}, where the nodes are in
+//!  order with each directory before its contents, i.e. {"key":"jobs","dir":true} then {"key":"jobs/1","value":..}.
+//!  The keys are relative to the directory which was backed up, and the ttls are those remaining when it was taken.
+
+use rustc_serialize::json::Json;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use etcd::{decode, EtcdClient};
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_node::EtcdNode;
+
+/// identifies the files written by backup()
+static FORMAT: &'static str = "etcd-rs-backup";
+
+/// the version of the file format, restore() only reads this version
+static FORMAT_VERSION: u64 = 1;
+
+/// What backup() wrote
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackupSummary {
+    /// the etcd index the backup was taken at, it's a consistent snapshot as of this index
+    pub index: u64,
+    pub keys: usize,
+    pub dirs: usize,
+}
+
+/// What restore() does with a key which already exists
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// keeps the existing key
+    Skip,
+    /// replaces the existing key with the one from the backup. A file where the backup has a directory, or a
+    ///  directory where it has a file, is kept and skipped along with the keys beneath it, rather than removed.
+    Overwrite,
+    /// stops the restore with the NodeExist error, the keys restored before it are kept
+    Fail,
+}
+
+/// How restore() recreates the backup
+#[derive(Clone, Debug)]
+pub struct RestorePolicy {
+    pub conflict: ConflictPolicy,
+    /// (from, to) prefixes of the relative keys, the first which matches a key is replaced
+    pub rewrites: Vec<(String, String)>,
+}
+
+impl RestorePolicy {
+    pub fn new(conflict: ConflictPolicy) -> RestorePolicy {
+        return RestorePolicy{ conflict: conflict, rewrites: vec![] };
+    }
+
+    /// restores the keys beginning with from as beginning with to, i.e. rewrite("jobs/", "archived-jobs/")
+    pub fn rewrite(mut self, from: &str, to: &str) -> RestorePolicy {
+        self.rewrites.push((from.to_string(), to.to_string()));
+        return self;
+    }
+
+    fn rewritten(&self, key: &str) -> String {
+        for &(ref from, ref to) in &self.rewrites {
+            if key.starts_with(from as &str) {
+                return format!("{}{}", to, &key[from.len()..]);
+            }
+        }
+
+        return key.to_string();
+    }
+}
+
+/// What restore() did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RestoreSummary {
+    pub created: usize,
+    pub overwritten: usize,
+    pub skipped: usize,
+}
+
+/// writes the directory and everything beneath it, see EtcdClient::backup()
+pub fn backup<W: Write>(client: &EtcdClient, dir: &str, writer: &mut W) -> Result<BackupSummary, EtcdError> {
+    let result = try!(client.get_result(dir, true, true));
+    let root = try!(result.node.ok_or(EtcdError::MissingField{ field: "node".to_string(), key: Some(dir.to_string()) }));
+
+    // a file would be backed up as an empty directory
+    if !root.dir {
+        return Err(EtcdError::Api{ code: EtcdErrorCode::NotDir,
+                                   message: "only a directory can be backed up".to_string(),
+                                   cause: Some(root.key.clone()),
+                                   index: result.x_etcd_index });
+    }
+
+    let mut nodes: Vec<Json> = vec![];
+    let mut summary = BackupSummary{ index: result.x_etcd_index, keys: 0, dirs: 0 };
+    let prefix = if root.key == "/" { "/".to_string() } else { format!("{}/", root.key) };
+
+    for child in root.nodes.iter().flat_map(|n| n.iter()) {
+        add_node(child, &prefix, &mut nodes, &mut summary);
+    }
+
+    let mut obj = BTreeMap::new();
+    obj.insert("format".to_string(), Json::String(FORMAT.to_string()));
+    obj.insert("version".to_string(), Json::U64(FORMAT_VERSION));
+    obj.insert("dir".to_string(), Json::String(root.key.clone()));
+    obj.insert("index".to_string(), Json::U64(summary.index));
+    obj.insert("nodes".to_string(), Json::Array(nodes));
+
+    try!(write!(writer, "{}", Json::Object(obj).pretty()));
+    info!("backed up {} keys and {} directories of {} at index {}", summary.keys, summary.dirs, root.key, summary.index);
+
+    return Ok(summary);
+}
+
+/// appends the node and then its children, with keys relative to the directory being backed up
+fn add_node(node: &EtcdNode, prefix: &str, nodes: &mut Vec<Json>, summary: &mut BackupSummary) {
+    let mut obj = BTreeMap::new();
+    obj.insert("key".to_string(), Json::String(node.key[prefix.len()..].to_string()));
+
+    // a key about to expire is restored with the shortest ttl rather than none
+    if let Some(ttl) = node.ttl {
+        obj.insert("ttl".to_string(), Json::U64(cmp::max(ttl, 1) as u64));
+    }
+
+    if node.dir {
+        obj.insert("dir".to_string(), Json::Boolean(true));
+        summary.dirs += 1;
+    } else {
+        obj.insert("value".to_string(), Json::String(node.value.clone().unwrap_or(String::new())));
+        summary.keys += 1;
+    }
+
+    nodes.push(Json::Object(obj));
+
+    for child in node.nodes.iter().flat_map(|n| n.iter()) {
+        add_node(child, prefix, nodes, summary);
+    }
+}
+
+/// recreates the backup beneath the target directory, see EtcdClient::restore()
+pub fn restore<R: Read>(client: &EtcdClient, reader: &mut R, target_dir: &str, policy: &RestorePolicy) -> Result<RestoreSummary, EtcdError> {
+    let backup_json = try!(Json::from_reader(reader));
+    let obj = try!(decode::as_object(&backup_json, "backup", None));
+
+    let format = try!(decode::optional(obj, "format", None, decode::as_string));
+    let version = try!(decode::optional(obj, "version", None, decode::as_u64));
+    if format.as_ref().map(|f| f as &str) != Some(FORMAT) || version != Some(FORMAT_VERSION) {
+        return Err(EtcdError::InvalidField{ field: "version".to_string(), key: None, expected: "an etcd-rs-backup of version 1" });
+    }
+
+    let target = target_dir.trim_matches('/');
+    let mut summary = RestoreSummary{ created: 0, overwritten: 0, skipped: 0 };
+
+    for node_json in try!(decode::as_array(try!(decode::required(obj, "nodes", None)), "nodes", None)) {
+        let node = try!(decode::as_object(node_json, "nodes", None));
+        let relative = try!(decode::as_string(try!(decode::required(node, "key", None)), "key", None));
+        let k = Some(&relative as &str);
+
+        let key = format!("{}/{}", target, policy.rewritten(&relative));
+        let ttl = try!(decode::optional(node, "ttl", k, decode::as_u64));
+
+        if try!(decode::optional(node, "dir", k, decode::as_bool)).unwrap_or(false) {
+            try!(restore_dir(client, &key, ttl, policy, &mut summary));
+        } else {
+            let value = try!(decode::as_string(try!(decode::required(node, "value", k)), "value", k));
+            try!(restore_key(client, &key, &value, ttl, policy, &mut summary));
+        }
+    }
+
+    info!("restored {} keys to {}, {} were overwritten and {} skipped", summary.created, target_dir, summary.overwritten, summary.skipped);
+    return Ok(summary);
+}
+
+/// an existing directory is kept, as the keys restored into it may be alongside others
+fn restore_dir(client: &EtcdClient, dir: &str, ttl: Option<u64>, policy: &RestorePolicy, summary: &mut RestoreSummary) -> Result<(), EtcdError> {
+    return match client.create_dir(dir, ttl) {
+        Ok(..) => {
+            summary.created += 1;
+            Ok(())
+        },
+        Err(EtcdError::Api{ code: EtcdErrorCode::NodeExist, .. }) if policy.conflict == ConflictPolicy::Overwrite => {
+            // updating a file as a directory would empty it
+            if !try!(client.get(dir)).map_or(true, |node| node.dir) {
+                return Ok(skip_conflict(dir, "a file", summary));
+            }
+
+            try!(client.update_dir(dir, ttl));
+            summary.overwritten += 1;
+            Ok(())
+        },
+        Err(EtcdError::Api{ code: EtcdErrorCode::NodeExist, .. }) => {
+            summary.skipped += 1;
+            Ok(())
+        },
+        Err(EtcdError::Api{ code: EtcdErrorCode::NotDir, .. }) if policy.conflict != ConflictPolicy::Fail => {
+            Ok(skip_conflict(dir, "beneath a file", summary))
+        },
+        Err(e) => Err(e),
+    };
+}
+
+fn restore_key(client: &EtcdClient, key: &str, value: &str, ttl: Option<u64>, policy: &RestorePolicy, summary: &mut RestoreSummary) -> Result<(), EtcdError> {
+    let result = if policy.conflict == ConflictPolicy::Overwrite {
+        client.set(key, value, ttl)
+    } else {
+        client.create(key, value, ttl).map(|_| None)
+    };
+
+    return match result {
+        Ok(Some(..)) => {
+            summary.overwritten += 1;
+            Ok(())
+        },
+        Ok(None) => {
+            summary.created += 1;
+            Ok(())
+        },
+        Err(EtcdError::Api{ code: EtcdErrorCode::NodeExist, .. }) if policy.conflict == ConflictPolicy::Skip => {
+            debug!("{} exists, skipped", key);
+            summary.skipped += 1;
+            Ok(())
+        },
+        Err(EtcdError::Api{ code: EtcdErrorCode::NotFile, .. }) if policy.conflict == ConflictPolicy::Overwrite => {
+            Ok(skip_conflict(key, "a directory", summary))
+        },
+        Err(EtcdError::Api{ code: EtcdErrorCode::NotDir, .. }) if policy.conflict != ConflictPolicy::Fail => {
+            Ok(skip_conflict(key, "beneath a file", summary))
+        },
+        Err(e) => Err(e),
+    };
+}
+
+/// the existing node is of the other type than the one in the backup, it's kept rather than removed with its contents
+fn skip_conflict(key: &str, existing: &str, summary: &mut RestoreSummary) {
+    warn!("{} is {} in the cluster, skipped", key, existing);
+    summary.skipped += 1;
+}
+
+#[cfg(test)]
+mod tests {
+  use rustc_serialize::json::Json;
+  use super::{ConflictPolicy, RestorePolicy, RestoreSummary};
+  use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+  use testing::MockTransport;
+
+  #[test]
+  fn backup_restore_test() {
+    let client = MockTransport::new().client();
+    client.set("/backup/jobs/1", "one", None).unwrap();
+    client.set("/backup/jobs/2", "two", Some(60)).unwrap();
+    client.create_dir("/backup/empty", Some(60)).unwrap();
+    client.set("/backup/top", "top", None).unwrap();
+
+    let mut file: Vec<u8> = vec![];
+    let backup = client.backup("/backup", &mut file).unwrap();
+    assert_eq!(backup.keys, 3);
+    assert_eq!(backup.dirs, 2);
+
+    let json = Json::from_str(&String::from_utf8(file.clone()).unwrap()).unwrap();
+    assert_eq!(json.find("dir").unwrap().as_string(), Some("/backup"));
+    assert_eq!(json.find("index").unwrap().as_u64(), Some(backup.index));
+
+    let summary = client.restore(&mut &file[..], "/restored", &RestorePolicy::new(ConflictPolicy::Fail)).unwrap();
+    assert_eq!(summary, RestoreSummary{ created: 5, overwritten: 0, skipped: 0 });
+
+    assert_eq!(client.get("/restored/jobs/1").unwrap().unwrap().value.unwrap(), "one");
+    assert!(client.get("/restored/jobs/2").unwrap().unwrap().ttl.is_some());
+    assert!(client.get("/restored/empty").unwrap().unwrap().dir);
+    assert_eq!(client.get("/restored/top").unwrap().unwrap().value.unwrap(), "top");
+  }
+
+  #[test]
+  fn restore_conflict_test() {
+    let client = MockTransport::new().client();
+    client.set("/backup/jobs/1", "one", None).unwrap();
+    client.set("/backup/jobs/2", "two", None).unwrap();
+
+    let mut file: Vec<u8> = vec![];
+    client.backup("/backup", &mut file).unwrap();
+    client.set("/backup/jobs/1", "changed", None).unwrap();
+
+    let skipped = client.restore(&mut &file[..], "/backup", &RestorePolicy::new(ConflictPolicy::Skip)).unwrap();
+    assert_eq!(skipped, RestoreSummary{ created: 0, overwritten: 0, skipped: 3 });
+    assert_eq!(client.get("/backup/jobs/1").unwrap().unwrap().value.unwrap(), "changed");
+
+    match client.restore(&mut &file[..], "/backup", &RestorePolicy::new(ConflictPolicy::Fail)) {
+      Err(EtcdError::Api{ code, .. }) => assert_eq!(code, EtcdErrorCode::NodeExist),
+      r => panic!("expected NodeExist: {:?}", r),
+    }
+
+    let overwritten = client.restore(&mut &file[..], "/backup", &RestorePolicy::new(ConflictPolicy::Overwrite)).unwrap();
+    assert_eq!(overwritten, RestoreSummary{ created: 0, overwritten: 3, skipped: 0 });
+    assert_eq!(client.get("/backup/jobs/1").unwrap().unwrap().value.unwrap(), "one");
+  }
+
+  #[test]
+  fn restore_type_conflict_test() {
+    let client = MockTransport::new().client();
+    client.set("/backup/jobs/1", "one", None).unwrap();
+    client.set("/backup/top", "top", None).unwrap();
+
+    let mut file: Vec<u8> = vec![];
+    client.backup("/backup", &mut file).unwrap();
+
+    // jobs is now a file and top a directory
+    client.remove_dir("/backup/jobs", true).unwrap();
+    client.set("/backup/jobs", "file", None).unwrap();
+    client.remove("/backup/top").unwrap();
+    client.set("/backup/top/nested", "nested", None).unwrap();
+
+    let overwritten = client.restore(&mut &file[..], "/backup", &RestorePolicy::new(ConflictPolicy::Overwrite)).unwrap();
+    assert_eq!(overwritten, RestoreSummary{ created: 0, overwritten: 0, skipped: 3 });
+    assert_eq!(client.get("/backup/jobs").unwrap().unwrap().value.unwrap(), "file");
+    assert_eq!(client.get("/backup/top/nested").unwrap().unwrap().value.unwrap(), "nested");
+
+    let skipped = client.restore(&mut &file[..], "/backup", &RestorePolicy::new(ConflictPolicy::Skip)).unwrap();
+    assert_eq!(skipped, RestoreSummary{ created: 0, overwritten: 0, skipped: 3 });
+  }
+
+  #[test]
+  fn backup_file_test() {
+    let client = MockTransport::new().client();
+    client.set("/backup/top", "top", None).unwrap();
+
+    let mut file: Vec<u8> = vec![];
+    match client.backup("/backup/top", &mut file) {
+      Err(EtcdError::Api{ code, .. }) => assert_eq!(code, EtcdErrorCode::NotDir),
+      r => panic!("expected NotDir: {:?}", r),
+    }
+
+    assert!(file.is_empty());
+  }
+
+  #[test]
+  fn restore_rewrite_test() {
+    let client = MockTransport::new().client();
+    client.set("/backup/jobs/1", "one", None).unwrap();
+    client.set("/backup/other", "other", None).unwrap();
+
+    let mut file: Vec<u8> = vec![];
+    client.backup("/backup", &mut file).unwrap();
+
+    let policy = RestorePolicy::new(ConflictPolicy::Fail).rewrite("jobs", "archived-jobs");
+    client.restore(&mut &file[..], "/restored", &policy).unwrap();
+
+    assert_eq!(client.get("/restored/archived-jobs/1").unwrap().unwrap().value.unwrap(), "one");
+    assert_eq!(client.get("/restored/other").unwrap().unwrap().value.unwrap(), "other");
+  }
+
+  #[test]
+  fn restore_invalid_test() {
+    let client = MockTransport::new().client();
+
+    match client.restore(&mut "{\"format\": \"tarball\"}".as_bytes(), "/restored", &RestorePolicy::new(ConflictPolicy::Fail)) {
+      Err(EtcdError::InvalidField{ ref field, .. }) => assert_eq!(field as &str, "version"),
+      r => panic!("expected InvalidField: {:?}", r),
+    }
+  }
+}
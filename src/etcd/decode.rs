@@ -0,0 +1,51 @@
+//! Helpers for decoding the json responses of etcd, these name the field and the key of the node which was
+//!  being decoded in the error.
+
+use rustc_serialize::json;
+use etcd::etcd_error::EtcdError;
+
+fn invalid(field: &str, key: Option<&str>, expected: &'static str) -> EtcdError {
+    return EtcdError::InvalidField{ field: field.to_string(), key: key.map(|k| k.to_string()), expected: expected };
+}
+
+/// returns the field, or MissingField if it's not present
+pub fn required<'a>(obj: &'a json::Object, field: &str, key: Option<&str>) -> Result<&'a json::Json, EtcdError> {
+    return obj.get(field).ok_or(EtcdError::MissingField{ field: field.to_string(), key: key.map(|k| k.to_string()) });
+}
+
+pub fn as_object<'a>(j: &'a json::Json, field: &str, key: Option<&str>) -> Result<&'a json::Object, EtcdError> {
+    return j.as_object().ok_or(invalid(field, key, "an object"));
+}
+
+pub fn as_array<'a>(j: &'a json::Json, field: &str, key: Option<&str>) -> Result<&'a json::Array, EtcdError> {
+    return j.as_array().ok_or(invalid(field, key, "an array"));
+}
+
+pub fn as_string(j: &json::Json, field: &str, key: Option<&str>) -> Result<String, EtcdError> {
+    return j.as_string().map(|s| s.to_string()).ok_or(invalid(field, key, "a string"));
+}
+
+pub fn as_i64(j: &json::Json, field: &str, key: Option<&str>) -> Result<i64, EtcdError> {
+    return j.as_i64().ok_or(invalid(field, key, "an integer"));
+}
+
+pub fn as_u64(j: &json::Json, field: &str, key: Option<&str>) -> Result<u64, EtcdError> {
+    return j.as_u64().ok_or(invalid(field, key, "an unsigned integer"));
+}
+
+pub fn as_f64(j: &json::Json, field: &str, key: Option<&str>) -> Result<f64, EtcdError> {
+    return j.as_f64().ok_or(invalid(field, key, "a number"));
+}
+
+pub fn as_bool(j: &json::Json, field: &str, key: Option<&str>) -> Result<bool, EtcdError> {
+    return j.as_boolean().ok_or(invalid(field, key, "a boolean"));
+}
+
+/// returns None if the field is not present, otherwise the decoded field
+pub fn optional<'a, T, F>(obj: &'a json::Object, field: &str, key: Option<&str>, decode: F) -> Result<Option<T>, EtcdError>
+where F: Fn(&'a json::Json, &str, Option<&str>) -> Result<T, EtcdError> {
+    return match obj.get(field) {
+        Some(j) => decode(j, field, key).map(|v| Some(v)),
+        None => Ok(None),
+    };
+}
@@ -0,0 +1,233 @@
+use hyper;
+use std::path::Path;
+use std::time::Duration;
+use etcd::EtcdClient;
+use etcd::etcd_error::EtcdError;
+use etcd::etcd_tls::EtcdTls;
+use etcd::retry::RetryPolicy;
+use etcd::transport::{ConnectionOptions, HyperTransport, Transport};
+
+/// Builder for the EtcdClient, collects the cluster members to connect to.
+///
+/// ```no_run
+/// use etcd_rs::etcd::EtcdClientBuilder;
+///
+/// let client = EtcdClientBuilder::new()
+///                .endpoint("http://10.0.0.1:4001")
+///                .endpoint("http://10.0.0.2:4001")
+///                .build()
+///                .unwrap();
+/// ```
+///
+/// https endpoints are verified against the system CAs unless ca_file is specified,
+///  and the client_cert is presented to members which require mutual authentication.
+///
+/// ```no_run
+/// use etcd_rs::etcd::EtcdClientBuilder;
+///
+/// let client = EtcdClientBuilder::new()
+///                .endpoint("https://10.0.0.1:4001")
+///                .ca_file("/etc/etcd/ca.pem")
+///                .client_cert("/etc/etcd/client.pem", "/etc/etcd/client-key.pem")
+///                .build()
+///                .unwrap();
+/// ```
+///
+/// A member which doesn't answer in time is treated like one which can't be reached, and idempotent requests
+///  are retried after transient failures according to the RetryPolicy.
+///
+/// ```no_run
+/// use etcd_rs::etcd::EtcdClientBuilder;
+/// use etcd_rs::etcd::retry::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let client = EtcdClientBuilder::new()
+///                .endpoint("http://10.0.0.1:4001")
+///                .connect_timeout(Duration::from_secs(1))
+///                .read_timeout(Duration::from_secs(5))
+///                .retry_policy(RetryPolicy::new(3))
+///                .build()
+///                .unwrap();
+/// ```
+pub struct EtcdClientBuilder {
+    endpoints: Vec<String>,
+    tls: EtcdTls,
+    options: ConnectionOptions,
+    retry: RetryPolicy,
+    transport: Option<Box<Transport>>,
+    negotiate: bool,
+}
+
+impl EtcdClientBuilder {
+    pub fn new() -> EtcdClientBuilder {
+        return EtcdClientBuilder{ endpoints: vec![], tls: EtcdTls::new(), options: ConnectionOptions::new(),
+                                 retry: RetryPolicy::none(), transport: None, negotiate: true };
+    }
+
+    /// add a member url, i.e. http://host:port, requests fail over to the members in the order they were added
+    pub fn endpoint(mut self, url: &str) -> EtcdClientBuilder {
+        self.endpoints.push(url.to_string());
+        return self;
+    }
+
+    /// add a list of member urls, see endpoint()
+    pub fn endpoints(mut self, urls: &[&str]) -> EtcdClientBuilder {
+        for url in urls {
+            self.endpoints.push(url.to_string());
+        }
+
+        return self;
+    }
+
+    /// the PEM bundle of CAs which may sign the member certificates
+    pub fn ca_file<P: AsRef<Path>>(mut self, ca_file: P) -> EtcdClientBuilder {
+        self.tls.ca_file = Some(ca_file.as_ref().to_path_buf());
+        return self;
+    }
+
+    /// the PEM certificate and private key this client authenticates with
+    pub fn client_cert<P: AsRef<Path>>(mut self, cert_file: P, key_file: P) -> EtcdClientBuilder {
+        self.tls.cert_file = Some(cert_file.as_ref().to_path_buf());
+        self.tls.key_file = Some(key_file.as_ref().to_path_buf());
+        return self;
+    }
+
+    /// false disables verification of the member certificates, defaults to true
+    pub fn verify_server(mut self, verify: bool) -> EtcdClientBuilder {
+        self.tls.verify = verify;
+        return self;
+    }
+
+    /// pins the name the member certificates must be issued to, by default it's the host of each endpoint
+    pub fn server_name(mut self, server_name: &str) -> EtcdClientBuilder {
+        self.tls.server_name = Some(server_name.to_string());
+        return self;
+    }
+
+    /// the idle connections kept open to each member for reuse, watches keep as many again, defaults to 5
+    pub fn max_idle(mut self, max_idle: usize) -> EtcdClientBuilder {
+        self.options.max_idle = max_idle;
+        return self;
+    }
+
+    /// how long to wait for a member to accept a connection before failing over to the next
+    pub fn connect_timeout(mut self, timeout: Duration) -> EtcdClientBuilder {
+        self.options.connect_timeout = Some(timeout);
+        return self;
+    }
+
+    /// how long to wait for a member to answer a request, by default there is no timeout
+    pub fn read_timeout(mut self, timeout: Duration) -> EtcdClientBuilder {
+        self.options.read_timeout = Some(timeout);
+        return self;
+    }
+
+    /// how long a watch waits for an event, by default there is no timeout. The Watcher reissues timed out watches.
+    pub fn watch_timeout(mut self, timeout: Duration) -> EtcdClientBuilder {
+        self.options.watch_timeout = Some(timeout);
+        return self;
+    }
+
+    /// how idempotent requests are retried after transient failures, by default they aren't
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> EtcdClientBuilder {
+        self.retry = retry;
+        return self;
+    }
+
+    /// sends the requests with the transport rather than hyper, the tls and pool options are then not used
+    pub fn transport(mut self, transport: Box<Transport>) -> EtcdClientBuilder {
+        self.transport = Some(transport);
+        return self;
+    }
+
+    /// the cluster is asked for its version when the client is built, and building fails if no member can be reached.
+    ///  false asks the first time a feature which needs it is used instead. Either way the features the cluster
+    ///  doesn't support fail with Unsupported rather than an error from etcd.
+    pub fn negotiate(mut self, negotiate: bool) -> EtcdClientBuilder {
+        self.negotiate = negotiate;
+        return self;
+    }
+
+    /// validates the endpoints and returns the client
+    pub fn build(self) -> Result<EtcdClient, EtcdError> {
+        if self.endpoints.is_empty() {
+            return Err(EtcdError::NoEndpoints);
+        }
+
+        let mut endpoints: Vec<String> = Vec::with_capacity(self.endpoints.len());
+        let mut https = false;
+        for endpoint in self.endpoints {
+            let url = try!(hyper::Url::parse(&endpoint));
+            match &url.scheme as &str {
+                "http" => (),
+                "https" => https = true,
+                _ => return Err(EtcdError::InvalidEndpoint(endpoint)),
+            }
+
+            // the path is appended to the endpoint when building requests
+            endpoints.push(endpoint.trim_right_matches('/').to_string());
+        }
+
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => {
+                // fail now rather than on the first request if the certificates can't be loaded
+                if https {
+                    try!(self.tls.validate());
+                }
+
+                Box::new(HyperTransport::new(self.tls, self.options)) as Box<Transport>
+            },
+        };
+
+        let client = EtcdClient::new(endpoints, transport, self.retry);
+        if self.negotiate {
+            try!(client.capabilities());
+        }
+
+        return Ok(client);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::EtcdClientBuilder;
+  use etcd::etcd_error::EtcdError;
+
+  #[test]
+  fn build_endpoints_test() {
+    let client = EtcdClientBuilder::new().endpoint("http://localhost:4001/")
+                                         .endpoints(&["http://10.0.0.1:4001", "http://10.0.0.2:4001"])
+                                         .negotiate(false)
+                                         .build()
+                                         .unwrap();
+
+    assert_eq!(client.endpoints(), &["http://localhost:4001".to_string(),
+                                     "http://10.0.0.1:4001".to_string(),
+                                     "http://10.0.0.2:4001".to_string()]);
+  }
+
+  #[test]
+  fn build_no_endpoints_test() {
+    match EtcdClientBuilder::new().build() {
+      Err(EtcdError::NoEndpoints) => (),
+      _ => panic!("expected NoEndpoints"),
+    }
+  }
+
+  #[test]
+  fn build_missing_ca_file_test() {
+    match EtcdClientBuilder::new().endpoint("https://localhost:4001").ca_file("/does/not/exist.pem").build() {
+      Err(EtcdError::TlsError(_)) => (),
+      _ => panic!("expected TlsError"),
+    }
+  }
+
+  #[test]
+  fn build_invalid_endpoint_test() {
+    match EtcdClientBuilder::new().endpoint("ftp://localhost:4001").build() {
+      Err(EtcdError::InvalidEndpoint(ref e)) if e == "ftp://localhost:4001" => (),
+      _ => panic!("expected InvalidEndpoint"),
+    }
+  }
+}
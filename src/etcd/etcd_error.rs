@@ -1,15 +1,246 @@
 use hyper;
+use hyper::error::HttpError;
+use openssl::ssl::error::SslError;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use std::io;
 use std::convert::From;
 use rustc_serialize::json;
+use url;
+use etcd::etcd_version::Version;
 
 #[derive(Debug)]
 pub enum EtcdError {
+  /// the server responded with an error status, but not an etcd error, e.g. from a proxy
   Unsuccessful(hyper::status::StatusCode),
+  /// the error returned by etcd
+  Api {
+    /// errorCode: the etcd error, see EtcdErrorCode
+    code: EtcdErrorCode,
+    /// message: the description of the error code
+    message: String,
+    /// cause: usually the key for which the request failed
+    cause: Option<String>,
+    /// index: the etcd index at the time of the error
+    index: u64,
+  },
+  /// the error returned by the cluster apis, i.e. /v2/members, these have a message but no etcd error code
+  Cluster {
+    status: hyper::status::StatusCode,
+    /// message: the description of the error, i.e. "etcdserver: member not found"
+    message: String,
+  },
+  /// the cluster runs a version of etcd without the feature, see EtcdClient::capabilities()
+  Unsupported {
+    feature: &'static str,
+    /// the first version with the feature
+    since: Version,
+    /// the version of the cluster
+    version: Version,
+  },
+  /// a required field was missing from the response
+  MissingField {
+    field: String,
+    /// the key of the node being decoded, if any
+    key: Option<String>,
+  },
+  /// a field of the response was not of the expected type
+  InvalidField {
+    field: String,
+    /// the key of the node being decoded, if any
+    key: Option<String>,
+    /// the type the field should have been
+    expected: &'static str,
+  },
+  /// etcd answered the watch without an event once its own timeout passed
+  WatchTimeout {
+    /// the X-Etcd-Index of the answer, the watch resumes from the index after it without missing a change
+    index: Option<u64>,
+  },
   HttpError(hyper::error::HttpError),
   IOError(io::Error),
   DecodingError(json::DecoderError),
   JsonParserError(json::ParserError),
+  UrlError(url::ParseError),
+  /// the client was built without any endpoints to connect to
+  NoEndpoints,
+  /// the endpoint is not a valid etcd member url, i.e. http://host:port
+  InvalidEndpoint(String),
+  /// the tls certificates or keys could not be loaded
+  TlsError(SslError),
+}
+
+impl EtcdError {
+  /// decodes the etcd error from the body of an unsuccessful response, i.e.
+  ///  {"errorCode":100,"message":"Key not found","cause":"/foo","index":4}, or {"message":..} from the cluster apis
+  pub fn from_response(status: hyper::status::StatusCode, body: &str) -> EtcdError {
+    let json_tree = match json::Json::from_str(body) {
+      Ok(j) => j,
+      Err(_) => return EtcdError::Unsuccessful(status),
+    };
+
+    let obj = match json_tree.as_object() {
+      Some(o) => o,
+      None => return EtcdError::Unsuccessful(status),
+    };
+
+    let code = match obj.get("errorCode").and_then(|j| j.as_u64()) {
+      Some(c) => c,
+      None => {
+        return match obj.get("message").and_then(|j| j.as_string()) {
+          Some(message) => EtcdError::Cluster{ status: status, message: message.to_string() },
+          None => EtcdError::Unsuccessful(status),
+        };
+      },
+    };
+
+    return EtcdError::Api {
+      code: EtcdErrorCode::from_code(code),
+      message: obj.get("message").and_then(|j| j.as_string()).unwrap_or("").to_string(),
+      cause: obj.get("cause").and_then(|j| j.as_string()).map(|s| s.to_string()),
+      index: obj.get("index").and_then(|j| j.as_u64()).unwrap_or(0),
+    };
+  }
+
+  /// the etcd error code, if this is an error returned by etcd
+  pub fn error_code(&self) -> Option<EtcdErrorCode> {
+    return match *self {
+      EtcdError::Api{ code, .. } => Some(code),
+      _ => None,
+    };
+  }
+}
+
+macro_rules! error_codes {
+  ($( $(#[$doc:meta])* $name:ident = $code:expr, )*) => (
+    /// The errorCode values of etcd, see https://github.com/coreos/etcd/blob/master/Documentation/errorcode.md
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum EtcdErrorCode {
+      $( $(#[$doc])* $name, )*
+      /// an error code not known to this client
+      Unknown(u64),
+    }
+
+    impl EtcdErrorCode {
+      pub fn from_code(code: u64) -> EtcdErrorCode {
+        return match code {
+          $( $code => EtcdErrorCode::$name, )*
+          _ => EtcdErrorCode::Unknown(code),
+        };
+      }
+
+      /// the numeric errorCode
+      pub fn code(&self) -> u64 {
+        return match *self {
+          $( EtcdErrorCode::$name => $code, )*
+          EtcdErrorCode::Unknown(code) => code,
+        };
+      }
+    }
+  )
+}
+
+error_codes! {
+  /// 100: the key does not exist
+  KeyNotFound = 100,
+  /// 101: compare failed, the prevValue or prevIndex did not match
+  TestFailed = 101,
+  /// 102: the key is a directory, not a file
+  NotFile = 102,
+  /// 103: reached the max number of peers in the cluster
+  NoMorePeer = 103,
+  /// 104: the key is a file, not a directory
+  NotDir = 104,
+  /// 105: the key already exists
+  NodeExist = 105,
+  /// 106: the prefix of the given key is a keyword in etcd
+  KeyIsPreserved = 106,
+  /// 107: the root is read only
+  RootROnly = 107,
+  /// 108: the directory is not empty
+  DirNotEmpty = 108,
+  /// 109: the peer address has existed
+  ExistingPeerAddr = 109,
+  /// 110: the request requires authentication
+  Unauthorized = 110,
+
+  /// 200: value is required in a post form
+  ValueRequired = 200,
+  /// 201: prevValue is required in a post form
+  PrevValueRequired = 201,
+  /// 202: the ttl is not a number
+  TTLNaN = 202,
+  /// 203: the index is not a number
+  IndexNaN = 203,
+  /// 204: value or ttl is required in a post form
+  ValueOrTTLRequired = 204,
+  /// 205: the timeout is not a number
+  TimeoutNaN = 205,
+  /// 206: name is required in a post form
+  NameRequired = 206,
+  /// 207: index or value is required
+  IndexOrValueRequired = 207,
+  /// 208: index and value cannot both be specified
+  IndexValueMutex = 208,
+  /// 209: invalid field
+  InvalidField = 209,
+  /// 210: invalid post form
+  InvalidForm = 210,
+  /// 211: value cannot be set while refreshing the ttl
+  RefreshValue = 211,
+  /// 212: a ttl is required to refresh
+  RefreshTTLRequired = 212,
+
+  /// 300: raft internal error
+  RaftInternal = 300,
+  /// 301: during leader election
+  LeaderElect = 301,
+
+  /// 400: the watcher was cleared due to an etcd recovery
+  WatcherCleared = 400,
+  /// 401: the event in the requested index is outdated and cleared
+  EventIndexCleared = 401,
+  /// 402: standby internal error
+  StandbyInternal = 402,
+  /// 403: invalid active size
+  InvalidActiveSize = 403,
+  /// 404: standby remove delay
+  InvalidRemoveDelay = 404,
+
+  /// 500: client internal error
+  ClientInternal = 500,
+}
+
+/// a message for people rather than the Debug of the error, the etcd errors are as etcdctl prints them, i.e.
+///  100: Key not found (/foo) [12]
+impl Display for EtcdError {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    return match *self {
+      EtcdError::Unsuccessful(ref status) => write!(f, "the server answered {} rather than etcd", status),
+      EtcdError::Api{ code, ref message, ref cause, index } => {
+        write!(f, "{}: {} ({}) [{}]", code.code(), message, cause.as_ref().map(|c| c as &str).unwrap_or(""), index)
+      },
+      EtcdError::Cluster{ ref status, ref message } => write!(f, "{} ({})", message, status),
+      EtcdError::Unsupported{ feature, since, version } => {
+        write!(f, "the cluster runs etcd {}, {} requires etcd {}", version, feature, since)
+      },
+      EtcdError::MissingField{ ref field, key: Some(ref key) } => write!(f, "the response is missing {} of {}", field, key),
+      EtcdError::MissingField{ ref field, key: None } => write!(f, "the response is missing {}", field),
+      EtcdError::InvalidField{ ref field, key: Some(ref key), expected } => write!(f, "{} of {} in the response is not {}", field, key, expected),
+      EtcdError::InvalidField{ ref field, key: None, expected } => write!(f, "{} in the response is not {}", field, expected),
+      EtcdError::WatchTimeout{ .. } => write!(f, "the watch timed out without an event"),
+      // the description of HttpIoError doesn't say what the io error was
+      EtcdError::HttpError(HttpError::HttpIoError(ref e)) => write!(f, "could not reach the server: {}", e),
+      EtcdError::HttpError(ref e) => write!(f, "invalid http response: {}", e),
+      EtcdError::IOError(ref e) => write!(f, "{}", e),
+      EtcdError::DecodingError(ref e) => write!(f, "could not decode the response: {}", e),
+      EtcdError::JsonParserError(ref e) => write!(f, "the response is not json: {}", e),
+      EtcdError::UrlError(ref e) => write!(f, "invalid url: {}", e),
+      EtcdError::NoEndpoints => write!(f, "no endpoints to connect to"),
+      EtcdError::InvalidEndpoint(ref endpoint) => write!(f, "{} is not an etcd member url, i.e. http://host:port", endpoint),
+      EtcdError::TlsError(ref e) => write!(f, "tls: {}", e),
+    };
+  }
 }
 
 impl From<hyper::error::HttpError> for EtcdError {
@@ -35,3 +266,79 @@ impl From<json::ParserError> for EtcdError {
 		EtcdError::JsonParserError(err)
 	}
 }
+
+impl From<url::ParseError> for EtcdError {
+    fn from(err: url::ParseError) -> EtcdError {
+		EtcdError::UrlError(err)
+	}
+}
+
+impl From<SslError> for EtcdError {
+    fn from(err: SslError) -> EtcdError {
+		EtcdError::TlsError(err)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::error::HttpError;
+  use hyper::status::StatusCode;
+  use std::io;
+  use super::{EtcdError, EtcdErrorCode};
+
+  static ERROR_JSON: &'static str = "{
+    \"errorCode\": 100,
+    \"message\": \"Key not found\",
+    \"cause\": \"/foo\",
+    \"index\": 4
+  }";
+
+  #[test]
+  fn decode_error_json_test() {
+    match EtcdError::from_response(StatusCode::NotFound, ERROR_JSON) {
+      EtcdError::Api{ code, message, cause, index } => {
+        assert_eq!(code, EtcdErrorCode::KeyNotFound);
+        assert_eq!(&message as &str, "Key not found");
+        assert_eq!(cause, Some("/foo".to_string()));
+        assert_eq!(index, 4);
+      },
+      e => panic!("expected Api error: {:?}", e),
+    }
+  }
+
+  #[test]
+  fn decode_not_etcd_error_test() {
+    match EtcdError::from_response(StatusCode::BadGateway, "<html>Bad Gateway</html>") {
+      EtcdError::Unsuccessful(StatusCode::BadGateway) => (),
+      e => panic!("expected Unsuccessful: {:?}", e),
+    }
+  }
+
+  #[test]
+  fn decode_cluster_error_test() {
+    match EtcdError::from_response(StatusCode::NotFound, "{\"message\":\"etcdserver: member not found\"}") {
+      EtcdError::Cluster{ status, message } => {
+        assert_eq!(status, StatusCode::NotFound);
+        assert_eq!(&message as &str, "etcdserver: member not found");
+      },
+      e => panic!("expected Cluster error: {:?}", e),
+    }
+  }
+
+  #[test]
+  fn display_test() {
+    assert_eq!(EtcdError::from_response(StatusCode::NotFound, ERROR_JSON).to_string(), "100: Key not found (/foo) [4]");
+    assert_eq!(EtcdError::Unsuccessful(StatusCode::BadGateway).to_string(), "the server answered 502 Bad Gateway rather than etcd");
+
+    let refused = EtcdError::from(HttpError::HttpIoError(io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused")));
+    assert_eq!(refused.to_string(), "could not reach the server: connection refused");
+  }
+
+  #[test]
+  fn error_code_test() {
+    assert_eq!(EtcdErrorCode::from_code(401), EtcdErrorCode::EventIndexCleared);
+    assert_eq!(EtcdErrorCode::EventIndexCleared.code(), 401);
+    assert_eq!(EtcdErrorCode::from_code(999), EtcdErrorCode::Unknown(999));
+    assert_eq!(EtcdErrorCode::Unknown(999).code(), 999);
+  }
+}
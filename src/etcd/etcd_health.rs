@@ -0,0 +1,143 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use etcd::etcd_error::EtcdError;
+use etcd::etcd_member::Member;
+
+/// The health of a member of the cluster, see EtcdClient::cluster_health()
+#[derive(Debug)]
+pub struct MemberHealth {
+  pub member: Member,
+
+  /// one of the client urls of the member answered
+  pub reachable: bool,
+
+  /// the member reported itself healthy on /health, i.e. it has a leader and is applying changes
+  pub healthy: bool,
+
+  /// the member is the raft leader
+  pub leader: bool,
+
+  /// the raft term the member is in, None if it couldn't be read
+  pub raft_term: Option<u64>,
+
+  /// the time /health took to answer
+  pub latency: Option<Duration>,
+
+  /// the error from the last client url tried, if the member couldn't be checked
+  pub error: Option<EtcdError>,
+
+  /// the check which failed with the error, the fields of the checks before it are set
+  pub failed_check: Option<MemberCheck>,
+}
+
+/// The requests made to check a member, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberCheck {
+  /// GET /health, sets reachable, latency and healthy
+  Health,
+  /// GET /v2/stats/self, sets leader
+  Stats,
+  /// GET /v2/keys/, sets raft_term
+  Keys,
+}
+
+impl Display for MemberCheck {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let path = match *self {
+      MemberCheck::Health => "/health",
+      MemberCheck::Stats => "/v2/stats/self",
+      MemberCheck::Keys => "/v2/keys/",
+    };
+
+    return write!(f, "{}", path);
+  }
+}
+
+impl MemberHealth {
+  /// the health of a member which hasn't been checked yet
+  pub fn new(member: Member) -> MemberHealth {
+    return MemberHealth{ member: member, reachable: false, healthy: false, leader: false, raft_term: None, latency: None,
+                         error: None, failed_check: None };
+  }
+}
+
+/// The overall verdict on the cluster
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClusterStatus {
+  /// all the members are healthy and agree on a single leader
+  Healthy,
+  /// a quorum of the members is healthy and there is a leader, the cluster accepts writes but can't lose another member
+  Degraded,
+  /// there is no quorum or no single leader, the cluster can't accept writes
+  Unhealthy,
+}
+
+/// The health of each member of the cluster, see EtcdClient::cluster_health()
+#[derive(Debug)]
+pub struct ClusterHealth {
+  pub members: Vec<MemberHealth>,
+  pub status: ClusterStatus,
+}
+
+impl ClusterHealth {
+  /// reaches the verdict from the health of the members
+  pub fn new(members: Vec<MemberHealth>) -> ClusterHealth {
+    let healthy = members.iter().filter(|m| m.healthy).count();
+    let leaders = members.iter().filter(|m| m.healthy && m.leader).count();
+    let quorum = members.len() / 2 + 1;
+
+    let status = if members.is_empty() || leaders != 1 || healthy < quorum {
+      ClusterStatus::Unhealthy
+    } else if healthy < members.len() {
+      ClusterStatus::Degraded
+    } else {
+      ClusterStatus::Healthy
+    };
+
+    return ClusterHealth{ members: members, status: status };
+  }
+
+  pub fn is_healthy(&self) -> bool {
+    return self.status == ClusterStatus::Healthy;
+  }
+
+  /// the member which is the leader, if there is one
+  pub fn leader(&self) -> Option<&MemberHealth> {
+    return self.members.iter().find(|m| m.healthy && m.leader);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{ClusterHealth, ClusterStatus, MemberHealth};
+  use etcd::etcd_member::Member;
+
+  fn member(id: &str, healthy: bool, leader: bool) -> MemberHealth {
+    let mut health = MemberHealth::new(Member{ id: id.to_string(), name: id.to_string(), peer_urls: vec![], client_urls: vec![] });
+    health.reachable = healthy;
+    health.healthy = healthy;
+    health.leader = leader;
+
+    return health;
+  }
+
+  #[test]
+  fn cluster_status_test() {
+    let healthy = ClusterHealth::new(vec![member("a", true, true), member("b", true, false), member("c", true, false)]);
+    assert_eq!(healthy.status, ClusterStatus::Healthy);
+    assert_eq!(&healthy.leader().unwrap().member.id as &str, "a");
+
+    let degraded = ClusterHealth::new(vec![member("a", true, true), member("b", true, false), member("c", false, false)]);
+    assert_eq!(degraded.status, ClusterStatus::Degraded);
+
+    let no_quorum = ClusterHealth::new(vec![member("a", true, true), member("b", false, false), member("c", false, false)]);
+    assert_eq!(no_quorum.status, ClusterStatus::Unhealthy);
+
+    let no_leader = ClusterHealth::new(vec![member("a", true, false), member("b", true, false), member("c", true, false)]);
+    assert_eq!(no_leader.status, ClusterStatus::Unhealthy);
+    assert!(no_leader.leader().is_none());
+
+    assert_eq!(ClusterHealth::new(vec![]).status, ClusterStatus::Unhealthy);
+  }
+}
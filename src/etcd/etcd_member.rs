@@ -0,0 +1,119 @@
+use rustc_serialize::json;
+use etcd::decode;
+use etcd::etcd_error::EtcdError;
+
+/// A member of the etcd cluster, as listed by /v2/members
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+  /// id: the hex id of the member, unique within the cluster
+  pub id: String,
+
+  /// name: the name the member was started with, empty until a member which was added has started
+  pub name: String,
+
+  /// peerURLs: the urls the other members reach this member on
+  pub peer_urls: Vec<String>,
+
+  /// clientURLs: the urls clients reach this member on, empty until a member which was added has started
+  pub client_urls: Vec<String>,
+}
+
+impl Member {
+  /// decodes the member, the error names the field which was missing or of the wrong type
+  pub fn from_json(obj: &json::Object) -> Result<Member, EtcdError> {
+    let id: String = try!(decode::as_string(try!(decode::required(obj, "id", None)), "id", None));
+
+    // the id is included in any errors from here on
+    let k = Some(&id as &str);
+
+    return Ok(Member {
+      name: try!(decode::optional(obj, "name", k, decode::as_string)).unwrap_or(String::new()),
+      peer_urls: try!(Member::urls(obj, "peerURLs", k)),
+      client_urls: try!(Member::urls(obj, "clientURLs", k)),
+      id: id.clone(),
+    });
+  }
+
+  /// decodes the list of urls, a missing list is empty
+  fn urls(obj: &json::Object, field: &str, key: Option<&str>) -> Result<Vec<String>, EtcdError> {
+    let arr: &Vec<json::Json> = match obj.get(field) {
+      Some(j) => try!(decode::as_array(j, field, key)),
+      None => return Ok(vec![]),
+    };
+
+    let mut urls: Vec<String> = Vec::with_capacity(arr.len());
+    for url in arr {
+      urls.push(try!(decode::as_string(url, field, key)));
+    }
+
+    return Ok(urls);
+  }
+
+  /// decodes the {"members":[..]} of /v2/members
+  pub fn list_from_json(obj: &json::Object) -> Result<Vec<Member>, EtcdError> {
+    let arr: &Vec<json::Json> = match obj.get("members") {
+      Some(j) => try!(decode::as_array(j, "members", None)),
+      // etcd omits the list rather than sending it empty
+      None => return Ok(vec![]),
+    };
+
+    let mut members: Vec<Member> = Vec::with_capacity(arr.len());
+    for m in arr {
+      members.push(try!(Member::from_json(try!(decode::as_object(m, "members", None)))));
+    }
+
+    return Ok(members);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rustc_serialize::json;
+  use super::Member;
+  use etcd::etcd_error::EtcdError;
+
+  static MEMBERS_JSON: &'static str = "{
+        \"members\": [
+            {
+                \"id\": \"272e204152\",
+                \"name\": \"infra1\",
+                \"peerURLs\": [\"http://10.0.0.10:2380\"],
+                \"clientURLs\": [\"http://10.0.0.10:2379\", \"http://10.0.0.10:4001\"]
+            },
+            {
+                \"id\": \"2225373f43\",
+                \"name\": \"\",
+                \"peerURLs\": [\"http://10.0.0.11:2380\"]
+            }
+        ]
+    }";
+
+  #[test]
+  fn decode_members_json_test() {
+    let json_tree = json::Json::from_str(MEMBERS_JSON).unwrap();
+    let members = Member::list_from_json(json_tree.as_object().unwrap()).unwrap();
+
+    assert_eq!(members.len(), 2);
+    assert_eq!(&members[0].id as &str, "272e204152");
+    assert_eq!(&members[0].name as &str, "infra1");
+    assert_eq!(members[0].peer_urls, vec!["http://10.0.0.10:2380".to_string()]);
+    assert_eq!(members[0].client_urls, vec!["http://10.0.0.10:2379".to_string(), "http://10.0.0.10:4001".to_string()]);
+
+    // a member which was added but hasn't started
+    assert_eq!(&members[1].name as &str, "");
+    assert!(members[1].client_urls.is_empty());
+  }
+
+  #[test]
+  fn decode_invalid_member_test() {
+    let json_tree = json::Json::from_str("{\"members\": [ { \"id\": \"272e204152\", \"peerURLs\": \"http://10.0.0.10:2380\" } ]}").unwrap();
+
+    match Member::list_from_json(json_tree.as_object().unwrap()) {
+      Err(EtcdError::InvalidField{ ref field, ref key, .. }) => {
+        assert_eq!(field as &str, "peerURLs");
+        assert_eq!(key, &Some("272e204152".to_string()));
+      },
+      r => panic!("expected InvalidField: {:?}", r),
+    }
+  }
+}
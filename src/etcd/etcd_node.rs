@@ -1,4 +1,6 @@
 use rustc_serialize::json;
+use etcd::decode;
+use etcd::etcd_error::EtcdError;
 //use chrono::datetime::DateTime;
 //use chrono::offset::fixed::FixedOffset;
 
@@ -36,27 +38,55 @@ pub struct EtcdNode {
 }
 
 impl EtcdNode {
-  pub fn from_json(obj: &json::Object) -> EtcdNode {
-	return EtcdNode {
-	  key: obj.get("key").unwrap().as_string().unwrap().to_string(),
-	  created_index: obj.get("createdIndex").unwrap().as_i64().unwrap(),
-	  modified_index: obj.get("modifiedIndex").unwrap().as_i64().unwrap(),
-	  value: if let Some(j) = obj.get("value") { Some(j.as_string().unwrap().to_string()) } else { None },
-      expiration: if let Some(j) = obj.get("expiration") { Some(j.as_string().unwrap().to_string()) } else { None },
-      ttl: if let Some(j) = obj.get("ttl") { j.as_i64() } else { None },
-      dir: if let Some(j) = obj.get("dir") { j.as_boolean().unwrap() } else { false },
-      nodes: if let Some(j) = obj.get("nodes") {
-         let arr: &Vec<json::Json> = j.as_array().unwrap();
-         let mut list: Vec<EtcdNode> = Vec::with_capacity(arr.len());
-
-         for n in arr {
-           // these should be node
-           list.push(EtcdNode::from_json(n.as_object().unwrap()));
-          }
-         Some(list) // the value
-        } else { None },
-     }
-   }
+  /// decodes the node, the error names the field which was missing or of the wrong type
+  pub fn from_json(obj: &json::Object) -> Result<EtcdNode, EtcdError> {
+    let dir: bool = try!(decode::optional(obj, "dir", None, decode::as_bool)).unwrap_or(false);
+
+    // etcd returns the root directory without a key or indexes
+    let root: bool = dir && !obj.contains_key("key");
+
+    let key: String = if root {
+      "/".to_string()
+    } else {
+      try!(decode::as_string(try!(decode::required(obj, "key", None)), "key", None))
+    };
+
+    // the key is included in any errors from here on
+    let k = Some(&key as &str);
+
+    let nodes: Option<Vec<EtcdNode>> = match obj.get("nodes") {
+      Some(j) => {
+        let arr: &Vec<json::Json> = try!(decode::as_array(j, "nodes", k));
+        let mut list: Vec<EtcdNode> = Vec::with_capacity(arr.len());
+
+        for n in arr {
+          // these should be node
+          list.push(try!(EtcdNode::from_json(try!(decode::as_object(n, "nodes", k)))));
+        }
+
+        Some(list) // the value
+      },
+      None => None,
+    };
+
+    let (created_index, modified_index) = if root {
+      (0, 0)
+    } else {
+      (try!(decode::as_i64(try!(decode::required(obj, "createdIndex", k)), "createdIndex", k)),
+       try!(decode::as_i64(try!(decode::required(obj, "modifiedIndex", k)), "modifiedIndex", k)))
+    };
+
+    return Ok(EtcdNode {
+      created_index: created_index,
+      modified_index: modified_index,
+      value: try!(decode::optional(obj, "value", k, decode::as_string)),
+      expiration: try!(decode::optional(obj, "expiration", k, decode::as_string)),
+      ttl: try!(decode::optional(obj, "ttl", k, decode::as_i64)),
+      dir: dir,
+      nodes: nodes,
+      key: key.clone(),
+    })
+  }
 }
 
 //// "20133-12-04T12:01:21.874888581-08:00"
@@ -77,6 +107,7 @@ impl EtcdNode {
 mod tests {
   use rustc_serialize::json;
   use super::EtcdNode;
+  use etcd::etcd_error::EtcdError;
 
   static NODE_JSON: &'static str = "{
                 \"createdIndex\": 2,
@@ -109,7 +140,7 @@ mod tests {
   #[test]
   fn decode_node_json_test() {
     let json_tree = json::Json::from_str(NODE_JSON).unwrap();
-    let etcd_node = EtcdNode::from_json(json_tree.as_object().unwrap());
+    let etcd_node = EtcdNode::from_json(json_tree.as_object().unwrap()).unwrap();
 
     assert_eq!(&etcd_node.key as &str, "/queue/2");
     assert_eq!(etcd_node.dir, false);
@@ -121,7 +152,7 @@ mod tests {
   #[test]
   fn decode_complex_node_json_test() {
     let json_tree = json::Json::from_str(COMPLEX_NODE_JSON).unwrap();
-    let etcd_node = EtcdNode::from_json(json_tree.as_object().unwrap());
+    let etcd_node = EtcdNode::from_json(json_tree.as_object().unwrap()).unwrap();
 
     assert_eq!(&etcd_node.key as &str, "/queue");
     assert_eq!(etcd_node.dir, true);
@@ -141,4 +172,76 @@ mod tests {
     assert_eq!((&nodes[1]).modified_index, 3);
     assert_eq!((&nodes[1]).value.as_ref().unwrap()as &str, "Job2");
   }
+
+  #[test]
+  fn decode_root_node_json_test() {
+    // the body of a get on "/", etcd leaves the key and indexes off the root
+    let json_tree = json::Json::from_str("{
+        \"action\": \"get\",
+        \"node\": {
+            \"dir\": true,
+            \"nodes\": [
+                { \"key\": \"/foo\", \"value\": \"bar\", \"modifiedIndex\": 4, \"createdIndex\": 4 },
+                { \"key\": \"/queue\", \"dir\": true, \"modifiedIndex\": 5, \"createdIndex\": 5 }
+            ]
+        }
+      }").unwrap();
+    let node_json = json_tree.as_object().unwrap().get("node").unwrap();
+    let etcd_node = EtcdNode::from_json(node_json.as_object().unwrap()).unwrap();
+
+    assert_eq!(&etcd_node.key as &str, "/");
+    assert_eq!(etcd_node.dir, true);
+    assert_eq!(etcd_node.created_index, 0);
+    assert_eq!(etcd_node.modified_index, 0);
+
+    let ref nodes = etcd_node.nodes.unwrap();
+
+    assert_eq!(&(&nodes[0]).key as &str, "/foo");
+    assert_eq!((&nodes[0]).modified_index, 4);
+    assert_eq!(&(&nodes[1]).key as &str, "/queue");
+    assert_eq!((&nodes[1]).dir, true);
+  }
+
+  #[test]
+  fn decode_missing_key_test() {
+    // only a directory may leave out its key
+    let json_tree = json::Json::from_str("{\"value\": \"bar\", \"modifiedIndex\": 4, \"createdIndex\": 4}").unwrap();
+
+    match EtcdNode::from_json(json_tree.as_object().unwrap()) {
+      Err(EtcdError::MissingField{ ref field, .. }) => assert_eq!(field as &str, "key"),
+      r => panic!("expected MissingField: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn decode_missing_field_test() {
+    let json_tree = json::Json::from_str("{\"key\": \"/queue/2\", \"modifiedIndex\": 2}").unwrap();
+
+    match EtcdNode::from_json(json_tree.as_object().unwrap()) {
+      Err(EtcdError::MissingField{ ref field, ref key }) => {
+        assert_eq!(field as &str, "createdIndex");
+        assert_eq!(key, &Some("/queue/2".to_string()));
+      },
+      r => panic!("expected MissingField: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn decode_invalid_field_test() {
+    let json_tree = json::Json::from_str("{
+        \"createdIndex\": 2,
+        \"dir\": true,
+        \"key\": \"/queue\",
+        \"modifiedIndex\": 2,
+        \"nodes\": [ { \"createdIndex\": 3, \"key\": \"/queue/3\", \"modifiedIndex\": \"three\" } ]
+      }").unwrap();
+
+    match EtcdNode::from_json(json_tree.as_object().unwrap()) {
+      Err(EtcdError::InvalidField{ ref field, ref key, .. }) => {
+        assert_eq!(field as &str, "modifiedIndex");
+        assert_eq!(key, &Some("/queue/3".to_string()));
+      },
+      r => panic!("expected InvalidField: {:?}", r),
+    }
+  }
 }
@@ -1,3 +1,5 @@
+use super::decode;
+use super::etcd_error::{EtcdError, EtcdErrorCode};
 use super::etcd_node::EtcdNode;
 use rustc_serialize::json;
 
@@ -15,52 +17,83 @@ pub struct EtcdResult {
   pub previous_node: Option<EtcdNode>,
 
   /// X-Etcd-Index is the current etcd index as explained above.
-  pub x_etcd_index: i64,
+  pub x_etcd_index: u64,
 
   /// X-Raft-Index is similar to the etcd index but is for the underlying raft protocol
-  pub x_raft_index: i64,
+  pub x_raft_index: u64,
 
   /// X-Raft-Term is an integer that will increase whenever an etcd master election happens in the cluster.
   ///   If this number is increasing rapidly, you may need to tune the election timeout.
-  pub x_raft_term: i64,
+  pub x_raft_term: u64,
 }
 
 impl EtcdResult {
-   pub fn from_json(obj: &json::Object) -> EtcdResult {
-	   let node = EtcdResult::node_from_json("node", obj);
-	   let prev_node = EtcdResult::node_from_json("prevNode", obj);
-	   let result_action: Option<&json::Json> = obj.get("action");
-
-	   return EtcdResult{
-	     action: result_action.unwrap().as_string().unwrap().to_string(),
+   /// decodes the result, the x_ fields are set from the headers of the response
+   pub fn from_json(obj: &json::Object) -> Result<EtcdResult, EtcdError> {
+	   let node = try!(EtcdResult::node_from_json("node", obj));
+	   let prev_node = try!(EtcdResult::node_from_json("prevNode", obj));
+	   let action = try!(decode::as_string(try!(decode::required(obj, "action", None)), "action", None));
+
+	   return Ok(EtcdResult{
+	     action: action,
 	     node: node,
 	     previous_node: prev_node,
-		 x_etcd_index: 0, // from headers...
+		 x_etcd_index: 0, // set from the response headers
 		 x_raft_index: 0,
 		 x_raft_term: 0,
-	   }
+	   })
+   }
+
+   /// the modifiedIndex of the node, for a watch this is the index of the event
+   pub fn modified_index(&self) -> Option<u64> {
+       return self.node.as_ref().map(|n| n.modified_index as u64);
    }
 
-   fn node_from_json(key: &'static str, result_obj: &json::Object) -> Option<EtcdNode> {
+   fn node_from_json(key: &'static str, result_obj: &json::Object) -> Result<Option<EtcdNode>, EtcdError> {
 	   // get the json for the node
 	   let node_obj: &json::Json = match result_obj.get(key) {
            Some(o) => o,
-           None => return None,
+           None => return Ok(None),
        };
 
 	   // extract the node
-       return match node_obj.as_object() {
-		   Some(o) => Some(EtcdNode::from_json(o)),
-		   None => None,
-		}
+       let node_obj = try!(decode::as_object(node_obj, key, None));
+       return EtcdNode::from_json(node_obj).map(|n| Some(n));
    }
 }
 
+/// The outcome of compare_and_swap and compare_and_delete
+#[derive(Debug)]
+pub enum CompareResult {
+  /// all of the conditions held and the operation was performed
+  Success(EtcdResult),
+
+  /// one of the conditions did not hold and nothing was changed
+  Failed {
+    /// TestFailed if the prevValue or prevIndex did not match, NodeExist if prevExist was false
+    code: EtcdErrorCode,
+    /// the conditions and the current value which was compared, as reported by etcd
+    cause: Option<String>,
+    /// the etcd index at the time of the comparison
+    index: u64,
+  },
+}
+
+impl CompareResult {
+  /// true if the conditions held
+  pub fn is_success(&self) -> bool {
+    return match *self {
+      CompareResult::Success(..) => true,
+      CompareResult::Failed{..} => false,
+    };
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use rustc_serialize::json;
   use super::EtcdResult;
-  use etcd::etcd_node::EtcdNode;
+  use etcd::etcd_error::EtcdError;
 
   static RESULT_JSON: &'static str = "{
     \"action\": \"expire\",
@@ -83,9 +116,10 @@ mod tests {
   #[test]
   fn decode_result_json_test() {
 	let json_tree = json::Json::from_str(RESULT_JSON).unwrap();
-	let etcd_result = EtcdResult::from_json(json_tree.as_object().unwrap());
+	let etcd_result = EtcdResult::from_json(json_tree.as_object().unwrap()).unwrap();
 
 	assert_eq!(etcd_result.action, "expire".to_string());
+	assert_eq!(etcd_result.modified_index(), Some(15));
 
 	let etcd_node = etcd_result.node.as_ref().unwrap();
 
@@ -103,4 +137,13 @@ mod tests {
 	assert_eq!(*etcd_prev_node.expiration.as_ref().unwrap(), "2013-12-11T10:39:35.689275857-08:00".to_string());
   }
 
+  #[test]
+  fn decode_missing_action_test() {
+	let json_tree = json::Json::from_str("{ \"node\": { \"createdIndex\": 8, \"key\": \"/dir\", \"modifiedIndex\": 15 } }").unwrap();
+
+	match EtcdResult::from_json(json_tree.as_object().unwrap()) {
+	  Err(EtcdError::MissingField{ ref field, .. }) => assert_eq!(field as &str, "action"),
+	  r => panic!("expected MissingField: {:?}", r),
+	}
+  }
 }
@@ -0,0 +1,329 @@
+use rustc_serialize::json;
+use std::collections::BTreeMap;
+use etcd::decode;
+use etcd::etcd_error::EtcdError;
+
+/// The stats of the leader, from /v2/stats/leader
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeaderStats {
+  /// leader: the id of the leader
+  pub leader: String,
+
+  /// followers: the stats of the replication to each of the followers, by id
+  pub followers: BTreeMap<String, FollowerStats>,
+}
+
+/// The stats of the replication from the leader to a follower
+#[derive(Clone, Debug, PartialEq)]
+pub struct FollowerStats {
+  /// latency: the round trip times of the requests to the follower, in milliseconds
+  pub latency: LatencyStats,
+
+  /// counts: the number of requests to the follower which failed and succeeded
+  pub counts: CountStats,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyStats {
+  pub current: f64,
+  pub average: f64,
+  pub standard_deviation: f64,
+  pub minimum: f64,
+  pub maximum: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountStats {
+  pub fail: u64,
+  pub success: u64,
+}
+
+/// The stats of the member which answered, from /v2/stats/self
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfStats {
+  /// name: the name the member was started with
+  pub name: String,
+
+  /// id: the hex id of the member
+  pub id: String,
+
+  /// state: the raft state of the member, i.e. StateLeader or StateFollower
+  pub state: String,
+
+  /// startTime: the time the member started
+  pub start_time: String,
+
+  /// leaderInfo: the leader as seen by the member
+  pub leader_info: LeaderInfo,
+
+  /// recvAppendRequestCnt: the number of append requests the member has received
+  pub recv_append_request_cnt: u64,
+
+  /// recvPkgRate: the requests received per second, only present while receiving
+  pub recv_pkg_rate: Option<f64>,
+
+  /// recvBandwidthRate: the bytes received per second, only present while receiving
+  pub recv_bandwidth_rate: Option<f64>,
+
+  /// sendAppendRequestCnt: the number of append requests the member has sent
+  pub send_append_request_cnt: u64,
+
+  /// sendPkgRate: the requests sent per second, only present on the leader while sending
+  pub send_pkg_rate: Option<f64>,
+
+  /// sendBandwidthRate: the bytes sent per second, only present on the leader while sending
+  pub send_bandwidth_rate: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeaderInfo {
+  /// leader: the id of the leader
+  pub leader: String,
+
+  /// uptime: how long the leader has led, i.e. 10m59.322358947s
+  pub uptime: String,
+
+  /// startTime: the time the leader became the leader
+  pub start_time: String,
+}
+
+/// The counts of the operations on the store since the member started, from /v2/stats/store. Each member counts
+///  only the requests it answered, except for the writes which are counted by every member as they are applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreStats {
+  pub gets_success: u64,
+  pub gets_fail: u64,
+  pub sets_success: u64,
+  pub sets_fail: u64,
+  pub delete_success: u64,
+  pub delete_fail: u64,
+  pub update_success: u64,
+  pub update_fail: u64,
+  pub create_success: u64,
+  pub create_fail: u64,
+  pub compare_and_swap_success: u64,
+  pub compare_and_swap_fail: u64,
+  pub compare_and_delete_success: u64,
+  pub compare_and_delete_fail: u64,
+  /// expireCount: the number of keys which expired
+  pub expire_count: u64,
+  /// watchers: the number of watches waiting for an event
+  pub watchers: u64,
+}
+
+/// decodes the field, which is required
+fn required<'a, T, F>(obj: &'a json::Object, field: &str, key: Option<&str>, decode: F) -> Result<T, EtcdError>
+where F: Fn(&'a json::Json, &str, Option<&str>) -> Result<T, EtcdError> {
+  return decode(try!(decode::required(obj, field, key)), field, key);
+}
+
+impl LeaderStats {
+  /// decodes the stats, the error names the field which was missing or of the wrong type
+  pub fn from_json(obj: &json::Object) -> Result<LeaderStats, EtcdError> {
+    let mut followers = BTreeMap::new();
+    if let Some(j) = obj.get("followers") {
+      for (id, follower) in try!(decode::as_object(j, "followers", None)) {
+        let follower = try!(decode::as_object(follower, "followers", Some(id as &str)));
+        followers.insert(id.clone(), try!(FollowerStats::from_json(follower, id)));
+      }
+    }
+
+    return Ok(LeaderStats {
+      leader: try!(required(obj, "leader", None, decode::as_string)),
+      followers: followers,
+    });
+  }
+}
+
+impl FollowerStats {
+  /// id is the id of the follower, it's included in any errors
+  fn from_json(obj: &json::Object, id: &str) -> Result<FollowerStats, EtcdError> {
+    let k = Some(id);
+    let latency = try!(decode::as_object(try!(decode::required(obj, "latency", k)), "latency", k));
+    let counts = try!(decode::as_object(try!(decode::required(obj, "counts", k)), "counts", k));
+
+    return Ok(FollowerStats {
+      latency: LatencyStats {
+        current: try!(required(latency, "current", k, decode::as_f64)),
+        average: try!(required(latency, "average", k, decode::as_f64)),
+        standard_deviation: try!(required(latency, "standardDeviation", k, decode::as_f64)),
+        minimum: try!(required(latency, "minimum", k, decode::as_f64)),
+        maximum: try!(required(latency, "maximum", k, decode::as_f64)),
+      },
+      counts: CountStats {
+        fail: try!(required(counts, "fail", k, decode::as_u64)),
+        success: try!(required(counts, "success", k, decode::as_u64)),
+      },
+    });
+  }
+}
+
+impl SelfStats {
+  /// decodes the stats, the error names the field which was missing or of the wrong type
+  pub fn from_json(obj: &json::Object) -> Result<SelfStats, EtcdError> {
+    let leader_info = try!(decode::as_object(try!(decode::required(obj, "leaderInfo", None)), "leaderInfo", None));
+
+    return Ok(SelfStats {
+      name: try!(required(obj, "name", None, decode::as_string)),
+      id: try!(required(obj, "id", None, decode::as_string)),
+      state: try!(required(obj, "state", None, decode::as_string)),
+      start_time: try!(required(obj, "startTime", None, decode::as_string)),
+      leader_info: LeaderInfo {
+        leader: try!(required(leader_info, "leader", None, decode::as_string)),
+        uptime: try!(required(leader_info, "uptime", None, decode::as_string)),
+        start_time: try!(required(leader_info, "startTime", None, decode::as_string)),
+      },
+      recv_append_request_cnt: try!(required(obj, "recvAppendRequestCnt", None, decode::as_u64)),
+      recv_pkg_rate: try!(decode::optional(obj, "recvPkgRate", None, decode::as_f64)),
+      recv_bandwidth_rate: try!(decode::optional(obj, "recvBandwidthRate", None, decode::as_f64)),
+      send_append_request_cnt: try!(required(obj, "sendAppendRequestCnt", None, decode::as_u64)),
+      send_pkg_rate: try!(decode::optional(obj, "sendPkgRate", None, decode::as_f64)),
+      send_bandwidth_rate: try!(decode::optional(obj, "sendBandwidthRate", None, decode::as_f64)),
+    });
+  }
+
+  /// true if the member is the raft leader
+  pub fn is_leader(&self) -> bool {
+    return self.state == "StateLeader";
+  }
+}
+
+impl StoreStats {
+  /// decodes the stats, the error names the field which was missing or of the wrong type
+  pub fn from_json(obj: &json::Object) -> Result<StoreStats, EtcdError> {
+    // older versions of etcd don't count all the operations
+    let count = |field: &str| -> Result<u64, EtcdError> {
+      return decode::optional(obj, field, None, decode::as_u64).map(|c| c.unwrap_or(0));
+    };
+
+    return Ok(StoreStats {
+      gets_success: try!(count("getsSuccess")),
+      gets_fail: try!(count("getsFail")),
+      sets_success: try!(count("setsSuccess")),
+      sets_fail: try!(count("setsFail")),
+      delete_success: try!(count("deleteSuccess")),
+      delete_fail: try!(count("deleteFail")),
+      update_success: try!(count("updateSuccess")),
+      update_fail: try!(count("updateFail")),
+      create_success: try!(count("createSuccess")),
+      create_fail: try!(count("createFail")),
+      compare_and_swap_success: try!(count("compareAndSwapSuccess")),
+      compare_and_swap_fail: try!(count("compareAndSwapFail")),
+      compare_and_delete_success: try!(count("compareAndDeleteSuccess")),
+      compare_and_delete_fail: try!(count("compareAndDeleteFail")),
+      expire_count: try!(count("expireCount")),
+      watchers: try!(count("watchers")),
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rustc_serialize::json;
+  use super::{LeaderStats, SelfStats, StoreStats};
+  use etcd::etcd_error::EtcdError;
+
+  static LEADER_JSON: &'static str = "{
+        \"leader\": \"924e2e83e93f2560\",
+        \"followers\": {
+            \"6e3bd23ae5f1eae0\": {
+                \"latency\": {
+                    \"current\": 0.000866,
+                    \"average\": 0.0016,
+                    \"standardDeviation\": 0.0017,
+                    \"minimum\": 0.000865,
+                    \"maximum\": 0.009
+                },
+                \"counts\": { \"fail\": 0, \"success\": 745 }
+            }
+        }
+    }";
+
+  static SELF_JSON: &'static str = "{
+        \"name\": \"node3\",
+        \"id\": \"ce2a822cea30bfca\",
+        \"state\": \"StateFollower\",
+        \"startTime\": \"2015-04-16T23:02:37.357963486-07:00\",
+        \"leaderInfo\": {
+            \"leader\": \"6e3bd23ae5f1eae0\",
+            \"uptime\": \"10m59.322358947s\",
+            \"startTime\": \"2015-04-16T23:02:38.357994658-07:00\"
+        },
+        \"recvAppendRequestCnt\": 5944,
+        \"recvBandwidthRate\": 570.6254930219969,
+        \"recvPkgRate\": 9.00892789741075,
+        \"sendAppendRequestCnt\": 0
+    }";
+
+  static STORE_JSON: &'static str = "{
+        \"compareAndSwapFail\": 1,
+        \"compareAndSwapSuccess\": 2,
+        \"createFail\": 0,
+        \"createSuccess\": 3,
+        \"deleteFail\": 0,
+        \"deleteSuccess\": 4,
+        \"expireCount\": 5,
+        \"getsFail\": 6,
+        \"getsSuccess\": 75,
+        \"setsFail\": 2,
+        \"setsSuccess\": 8,
+        \"updateFail\": 0,
+        \"updateSuccess\": 9,
+        \"watchers\": 1
+    }";
+
+  #[test]
+  fn decode_leader_stats_test() {
+    let json_tree = json::Json::from_str(LEADER_JSON).unwrap();
+    let stats = LeaderStats::from_json(json_tree.as_object().unwrap()).unwrap();
+
+    assert_eq!(&stats.leader as &str, "924e2e83e93f2560");
+
+    let follower = &stats.followers["6e3bd23ae5f1eae0"];
+    assert_eq!(follower.latency.current, 0.000866);
+    assert_eq!(follower.latency.maximum, 0.009);
+    assert_eq!(follower.counts.fail, 0);
+    assert_eq!(follower.counts.success, 745);
+  }
+
+  #[test]
+  fn decode_self_stats_test() {
+    let json_tree = json::Json::from_str(SELF_JSON).unwrap();
+    let stats = SelfStats::from_json(json_tree.as_object().unwrap()).unwrap();
+
+    assert_eq!(&stats.id as &str, "ce2a822cea30bfca");
+    assert!(!stats.is_leader());
+    assert_eq!(&stats.leader_info.leader as &str, "6e3bd23ae5f1eae0");
+    assert_eq!(stats.recv_append_request_cnt, 5944);
+    assert_eq!(stats.recv_pkg_rate, Some(9.00892789741075));
+    assert_eq!(stats.send_pkg_rate, None);
+  }
+
+  #[test]
+  fn decode_store_stats_test() {
+    let json_tree = json::Json::from_str(STORE_JSON).unwrap();
+    let stats = StoreStats::from_json(json_tree.as_object().unwrap()).unwrap();
+
+    assert_eq!(stats.gets_success, 75);
+    assert_eq!(stats.compare_and_swap_fail, 1);
+    assert_eq!(stats.expire_count, 5);
+    assert_eq!(stats.watchers, 1);
+    assert_eq!(stats.compare_and_delete_success, 0);
+  }
+
+  #[test]
+  fn decode_invalid_follower_test() {
+    let json_tree = json::Json::from_str("{
+        \"leader\": \"924e2e83e93f2560\",
+        \"followers\": { \"6e3bd23ae5f1eae0\": { \"latency\": {}, \"counts\": { \"fail\": 0, \"success\": 1 } } }
+      }").unwrap();
+
+    match LeaderStats::from_json(json_tree.as_object().unwrap()) {
+      Err(EtcdError::MissingField{ ref field, ref key }) => {
+        assert_eq!(field as &str, "current");
+        assert_eq!(key, &Some("6e3bd23ae5f1eae0".to_string()));
+      },
+      r => panic!("expected MissingField: {:?}", r),
+    }
+  }
+}
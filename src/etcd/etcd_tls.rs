@@ -0,0 +1,183 @@
+use hyper::net::ContextVerifier;
+use openssl::nid::Nid;
+use openssl::ssl::{SslContext, SslMethod, SSL_VERIFY_NONE, SSL_VERIFY_PEER};
+use openssl::ssl::error::SslError;
+use openssl::x509::{X509FileType, X509StoreContext};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// TLS options used for https endpoints.
+#[derive(Clone, Debug)]
+pub struct EtcdTls {
+    /// PEM bundle of the CAs to trust, the system defaults are used if not specified
+    pub ca_file: Option<PathBuf>,
+    /// PEM certificate presented to the server for mutual authentication
+    pub cert_file: Option<PathBuf>,
+    /// PEM private key of the cert_file
+    pub key_file: Option<PathBuf>,
+    /// false disables all verification of the server, this should only be used for testing
+    pub verify: bool,
+    /// the name the server certificate must be issued to, defaults to the host of the endpoint
+    pub server_name: Option<String>,
+}
+
+impl EtcdTls {
+    pub fn new() -> EtcdTls {
+        return EtcdTls{ ca_file: None, cert_file: None, key_file: None, verify: true, server_name: None };
+    }
+
+    /// returns the verifier for hyper, which configures the context for each connection to the host
+    pub fn verifier(&self, host: &str) -> ContextVerifier {
+        let tls = self.clone();
+        let host = host.to_string();
+
+        return Box::new(move |context: &mut SslContext| {
+            // the configuration was validated when the client was built, but the files may have changed since. Without
+            //  it the server can't be verified, so the connection is refused rather than made unverified.
+            if let Err(e) = tls.configure(context, &host) {
+                error!("could not configure tls for {}, refusing the connection: {:?}", host, e);
+                context.set_verify(SSL_VERIFY_PEER, Some(refuse_peer));
+            }
+        });
+    }
+
+    /// checks that the certificates and keys can be loaded
+    pub fn validate(&self) -> Result<(), SslError> {
+        let mut context = try!(SslContext::new(SslMethod::Sslv23));
+        return self.configure(&mut context, "localhost");
+    }
+
+    /// the verification is set up first, so that it's in place even if loading the files fails
+    fn configure(&self, context: &mut SslContext, host: &str) -> Result<(), SslError> {
+        if self.verify {
+            let server_name: String = self.server_name.clone().unwrap_or(host.to_string());
+            context.set_verify_with_data(SSL_VERIFY_PEER, verify_server_name, server_name);
+        } else {
+            context.set_verify(SSL_VERIFY_NONE, None);
+        }
+
+        match self.ca_file {
+            Some(ref ca_file) => try!(context.set_CA_file(ca_file)),
+            None => try!(context.set_default_verify_paths()),
+        }
+
+        if let Some(ref cert_file) = self.cert_file {
+            try!(context.set_certificate_file(cert_file, X509FileType::PEM));
+        }
+
+        if let Some(ref key_file) = self.key_file {
+            try!(context.set_private_key_file(key_file, X509FileType::PEM));
+            try!(context.check_private_key());
+        }
+
+        return Ok(());
+    }
+}
+
+/// fails every certificate, for a context whose configuration couldn't be loaded
+fn refuse_peer(_preverify_ok: bool, _x509_ctx: &X509StoreContext) -> bool {
+    return false;
+}
+
+/// verifies the chain with openssl and that the peer certificate was issued to the server_name
+fn verify_server_name(preverify_ok: bool, x509_ctx: &X509StoreContext, server_name: &String) -> bool {
+    if !preverify_ok {
+        return false;
+    }
+
+    // only the peer certificate, i.e. the end of the chain, carries the server name
+    if x509_ctx.error_depth() != 0 {
+        return true;
+    }
+
+    let cert = match x509_ctx.get_current_cert() {
+        Some(cert) => cert,
+        None => return false,
+    };
+
+    let mut dns_names: Vec<String> = vec![];
+    let mut ip_addresses: Vec<Vec<u8>> = vec![];
+    if let Some(alt_names) = cert.subject_alt_names() {
+        for alt_name in alt_names.iter() {
+            if let Some(dns_name) = alt_name.dnsname() {
+                dns_names.push(dns_name.to_string());
+            }
+
+            if let Some(ip_address) = alt_name.ipaddress() {
+                ip_addresses.push(ip_address.to_vec());
+            }
+        }
+    }
+
+    let common_name = cert.subject_name().text_by_nid(Nid::CN).map(|cn| (&cn as &str).to_string());
+    if matches_server_name(&dns_names, &ip_addresses, common_name.as_ref().map(|cn| cn as &str), server_name) {
+        return true;
+    }
+
+    warn!("server certificate for {:?} {:?} {:?} does not match {}", dns_names, ip_addresses, common_name, server_name);
+    return false;
+}
+
+/// true if the names the certificate was issued to include the server name. The subjectAltNames are checked, an
+///  ip address against the ip entries and a host against the dns entries, the common name only if there are none.
+fn matches_server_name(dns_names: &[String], ip_addresses: &[Vec<u8>], common_name: Option<&str>, server_name: &str) -> bool {
+    // an ipv6 host is bracketed in the url
+    let server_name = server_name.trim_left_matches('[').trim_right_matches(']');
+
+    if !dns_names.is_empty() || !ip_addresses.is_empty() {
+        return match server_name.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => ip_addresses.iter().any(|a| *a == ip.octets().to_vec()),
+            Ok(IpAddr::V6(ip)) => ip_addresses.iter().any(|a| *a == ip.octets().to_vec()),
+            Err(_) => dns_names.iter().any(|name| matches_dns_name(name, server_name)),
+        };
+    }
+
+    return common_name.map_or(false, |cn| matches_dns_name(cn, server_name));
+}
+
+/// compares the names ignoring case, a wildcard only matches the whole of the leftmost label, i.e. *.example.com
+fn matches_dns_name(name: &str, server_name: &str) -> bool {
+    let name = name.to_lowercase();
+    let server_name = server_name.to_lowercase();
+
+    if name.starts_with("*.") {
+        return match server_name.find('.') {
+            Some(dot) => dot > 0 && server_name[dot..] == name[1..],
+            None => false,
+        };
+    }
+
+    return name == server_name;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::matches_server_name;
+
+  fn names(names: &[&str]) -> Vec<String> {
+    return names.iter().map(|n| n.to_string()).collect();
+  }
+
+  #[test]
+  fn matches_alt_names_test() {
+    let dns_names = names(&["etcd.example.com", "*.etcd.example.com"]);
+    let ip_addresses = vec![vec![10, 0, 0, 1], vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]];
+
+    assert!(matches_server_name(&dns_names, &ip_addresses, None, "etcd.example.com"));
+    assert!(matches_server_name(&dns_names, &ip_addresses, None, "ETCD0.etcd.example.com"));
+    assert!(!matches_server_name(&dns_names, &ip_addresses, None, "a.b.etcd.example.com"));
+    assert!(matches_server_name(&dns_names, &ip_addresses, None, "10.0.0.1"));
+    assert!(matches_server_name(&dns_names, &ip_addresses, None, "[::1]"));
+    assert!(!matches_server_name(&dns_names, &ip_addresses, None, "10.0.0.2"));
+
+    // the common name isn't used once there are alt names
+    assert!(!matches_server_name(&dns_names, &ip_addresses, Some("other.example.com"), "other.example.com"));
+  }
+
+  #[test]
+  fn matches_common_name_test() {
+    assert!(matches_server_name(&[], &[], Some("etcd.example.com"), "etcd.example.com"));
+    assert!(!matches_server_name(&[], &[], Some("etcd.example.com"), "10.0.0.1"));
+    assert!(!matches_server_name(&[], &[], None, "etcd.example.com"));
+  }
+}
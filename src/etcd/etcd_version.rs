@@ -0,0 +1,198 @@
+use rustc_serialize::json;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use etcd::decode;
+use etcd::etcd_error::EtcdError;
+
+/// A release of etcd, i.e. 2.3.7, any pre-release or build suffix is ignored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+  pub major: u64,
+  pub minor: u64,
+  pub patch: u64,
+}
+
+impl Version {
+  pub fn new(major: u64, minor: u64, patch: u64) -> Version {
+    return Version{ major: major, minor: minor, patch: patch };
+  }
+
+  /// parses 2.3.7, v2.3.7, 3.0.0-beta.0 or 2.3.0+git, None if it's not a version
+  pub fn parse(version: &str) -> Option<Version> {
+    let version = version.trim().trim_left_matches('v');
+    let release = version.split(|c| c == '-' || c == '+').next().unwrap_or("");
+
+    let parts: Vec<u64> = match release.split('.').map(|p| p.parse::<u64>()).collect() {
+      Ok(parts) => parts,
+      Err(_) => return None,
+    };
+
+    return match parts.len() {
+      3 => Some(Version::new(parts[0], parts[1], parts[2])),
+      2 => Some(Version::new(parts[0], parts[1], 0)),
+      _ => None,
+    };
+  }
+}
+
+impl Display for Version {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    return write!(f, "{}.{}.{}", self.major, self.minor, self.patch);
+  }
+}
+
+/// The versions of the member which answered and of the cluster, from /version
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EtcdVersion {
+  /// etcdserver: the version of the member
+  pub server: Version,
+
+  /// etcdcluster: the version the whole cluster runs at, i.e. that of the oldest member. None before etcd 2.1, and
+  ///  while the cluster hasn't decided it.
+  pub cluster: Option<Version>,
+}
+
+impl EtcdVersion {
+  /// decodes {"etcdserver":"2.3.7","etcdcluster":"2.3.0"}, or the "etcd 2.0.13" of older versions
+  pub fn from_body(body: &str) -> Result<EtcdVersion, EtcdError> {
+    let version_json = match json::Json::from_str(body) {
+      Ok(j) => j,
+      Err(_) => {
+        let server = body.trim().trim_left_matches("etcd").trim();
+        return Ok(EtcdVersion{ server: try!(EtcdVersion::parse(server, "etcdserver")), cluster: None });
+      },
+    };
+
+    let obj = try!(decode::as_object(&version_json, "version", None));
+    let server = try!(decode::as_string(try!(decode::required(obj, "etcdserver", None)), "etcdserver", None));
+    let cluster = try!(decode::optional(obj, "etcdcluster", None, decode::as_string));
+
+    return Ok(EtcdVersion {
+      server: try!(EtcdVersion::parse(&server, "etcdserver")),
+      // "not_decided" until the members agree
+      cluster: cluster.and_then(|c| Version::parse(&c)),
+    });
+  }
+
+  fn parse(version: &str, field: &str) -> Result<Version, EtcdError> {
+    return Version::parse(version).ok_or(EtcdError::InvalidField{ field: field.to_string(), key: None, expected: "a version" });
+  }
+
+  /// the version the features of the cluster are decided by, a feature is only usable once every member has it
+  pub fn effective(&self) -> Version {
+    return self.cluster.unwrap_or(self.server);
+  }
+}
+
+/// The features of the etcd api which not every version supports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+  /// /v2/members, the members api
+  Members,
+  /// /health on each member
+  Health,
+  /// refreshing the ttl of a key without notifying watchers
+  Refresh,
+}
+
+impl Feature {
+  /// the first version which has the feature
+  pub fn since(&self) -> Version {
+    return match *self {
+      Feature::Members => Version::new(2, 0, 0),
+      Feature::Health => Version::new(2, 1, 0),
+      Feature::Refresh => Version::new(2, 3, 0),
+    };
+  }
+
+  pub fn name(&self) -> &'static str {
+    return match *self {
+      Feature::Members => "members",
+      Feature::Health => "health",
+      Feature::Refresh => "refresh",
+    };
+  }
+}
+
+static FEATURES: [Feature; 3] = [Feature::Members, Feature::Health, Feature::Refresh];
+
+/// The features the cluster supports, see EtcdClient::capabilities()
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capabilities {
+  pub version: EtcdVersion,
+  pub features: Vec<Feature>,
+}
+
+impl Capabilities {
+  pub fn new(version: EtcdVersion) -> Capabilities {
+    let effective = version.effective();
+    let features = FEATURES.iter().cloned().filter(|f| f.since() <= effective).collect();
+
+    return Capabilities{ version: version, features: features };
+  }
+
+  pub fn supports(&self, feature: Feature) -> bool {
+    return self.features.contains(&feature);
+  }
+
+  /// Unsupported if the cluster doesn't support the feature
+  pub fn require(&self, feature: Feature) -> Result<(), EtcdError> {
+    if self.supports(feature) {
+      return Ok(());
+    }
+
+    return Err(EtcdError::Unsupported{ feature: feature.name(), since: feature.since(), version: self.version.effective() });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Capabilities, EtcdVersion, Feature, Version};
+  use etcd::etcd_error::EtcdError;
+
+  #[test]
+  fn parse_version_test() {
+    assert_eq!(Version::parse("2.3.7"), Some(Version::new(2, 3, 7)));
+    assert_eq!(Version::parse("v0.4.6"), Some(Version::new(0, 4, 6)));
+    assert_eq!(Version::parse("3.0.0-beta.0"), Some(Version::new(3, 0, 0)));
+    assert_eq!(Version::parse("2.3.0+git"), Some(Version::new(2, 3, 0)));
+    assert_eq!(Version::parse("3.1"), Some(Version::new(3, 1, 0)));
+    assert_eq!(Version::parse("not_decided"), None);
+
+    assert!(Version::new(2, 3, 0) < Version::new(2, 10, 0));
+    assert_eq!(Version::new(2, 3, 7).to_string(), "2.3.7");
+  }
+
+  #[test]
+  fn decode_version_test() {
+    let version = EtcdVersion::from_body("{\"etcdserver\":\"2.3.7\",\"etcdcluster\":\"2.3.0\"}").unwrap();
+    assert_eq!(version.server, Version::new(2, 3, 7));
+    assert_eq!(version.cluster, Some(Version::new(2, 3, 0)));
+
+    let undecided = EtcdVersion::from_body("{\"etcdserver\":\"2.3.7\",\"etcdcluster\":\"not_decided\"}").unwrap();
+    assert_eq!(undecided.cluster, None);
+
+    let old = EtcdVersion::from_body("etcd 2.0.13").unwrap();
+    assert_eq!(old.server, Version::new(2, 0, 13));
+    assert_eq!(old.cluster, None);
+  }
+
+  #[test]
+  fn capabilities_test() {
+    // the cluster decides, not the member which answered
+    let capabilities = Capabilities::new(EtcdVersion{ server: Version::new(2, 3, 7), cluster: Some(Version::new(2, 2, 0)) });
+
+    assert!(capabilities.supports(Feature::Members));
+    assert!(capabilities.supports(Feature::Health));
+    assert!(!capabilities.supports(Feature::Refresh));
+
+    match capabilities.require(Feature::Refresh) {
+      Err(EtcdError::Unsupported{ feature, since, version }) => {
+        assert_eq!(feature, "refresh");
+        assert_eq!(since, Version::new(2, 3, 0));
+        assert_eq!(version, Version::new(2, 2, 0));
+      },
+      r => panic!("expected Unsupported: {:?}", r),
+    }
+  }
+}
@@ -0,0 +1,348 @@
+use hyper::error::HttpError;
+use std::cmp;
+use std::io;
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use std::thread::JoinHandle;
+use etcd::EtcdClient;
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_node::EtcdNode;
+use etcd::etcd_result::EtcdResult;
+use etcd::transport::{self, CancelToken};
+
+/// the action of the synthetic event emitted when the watched index was compacted and the key was re-read
+pub static RESYNC_ACTION: &'static str = "resync";
+
+/// Watcher continuously watches a key, see EtcdClient::watcher
+///
+/// Each call to next() blocks until the next change, resuming from the modifiedIndex of the previous event so that no
+///  changes are missed between calls. Connections closed or timed out during the long poll are reissued transparently,
+///  other errors are returned and the watch resumes from the same index on the next call.
+///
+/// If etcd has already cleared the events since the last index (errorCode 401), the key is read again and returned
+///  as an event with the action RESYNC_ACTION, the node is the current state of the key (or directory if recursive)
+///  and should replace any state built from the previous events. The node is None if the key no longer exists.
+pub struct Watcher<'a> {
+    client: &'a EtcdClient,
+    key: String,
+    recursive: bool,
+    wait_index: Option<u64>,
+    /// ends the iteration, see WatchHandle
+    cancel: Option<CancelToken>,
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(client: &'a EtcdClient, key: &str, wait_index: Option<u64>, recursive: bool) -> Watcher<'a> {
+        return Watcher{ client: client, key: key.to_string(), recursive: recursive, wait_index: wait_index, cancel: None };
+    }
+
+    fn is_cancelled(&self) -> bool {
+        return self.cancel.as_ref().map_or(false, |c| c.is_cancelled());
+    }
+
+    /// the index the next watch will start from
+    pub fn wait_index(&self) -> Option<u64> {
+        return self.wait_index;
+    }
+
+    /// runs the command on each change until the watch is cancelled or the command can't be run, see
+    ///  EtcdClient::exec_watch
+    ///
+    /// The command is the program followed by its arguments, it's run with the environment variables ETCD_WATCH_ACTION,
+    ///  ETCD_WATCH_KEY, ETCD_WATCH_VALUE and ETCD_WATCH_MODIFIED_INDEX of the event. Each run is waited for before
+    ///  the next event is read, the changes made in the meantime are read after it from the index the watch reached.
+    ///  A command which exits unsuccessfully is logged, the watch only ends if it can't be run at all. A watch which
+    ///  fails with a transient error, i.e. a member couldn't be reached or answered with a server error, is logged and
+    ///  resumed from the same index after the backoff of the client's RetryPolicy. Any other error ends the watch.
+    pub fn exec(mut self, command: &[&str]) -> Result<(), EtcdError> {
+        if command.is_empty() {
+            return Err(EtcdError::IOError(io::Error::new(io::ErrorKind::InvalidInput, "no command to run")));
+        }
+
+        // the consecutive failures, the backoff grows with them up to the max_backoff
+        let mut failures: u32 = 0;
+
+        while let Some(event) = self.next() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    if !is_transient(&e) {
+                        return Err(e);
+                    }
+
+                    let backoff = self.client.retry.backoff(failures);
+                    warn!("watch on {} failed, resuming from index {:?} in {:?}: {}", self.key, self.wait_index, backoff, e);
+
+                    failures = failures.saturating_add(1);
+                    thread::sleep(backoff);
+                    continue;
+                },
+            };
+
+            failures = 0;
+            let status = try!(event_command(command, &event).status());
+
+            if !status.success() {
+                warn!("{} exited with {} on {} of {:?}", command[0], status, event.action, event.node.as_ref().map(|n| &n.key));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// re-reads the key and resumes watching from the index it was read at
+    fn resync(&mut self) -> Result<EtcdResult, EtcdError> {
+        warn!("events for {} were cleared since index {:?}, resyncing", self.key, self.wait_index);
+
+        let mut result = match self.client.get_result(&self.key, self.recursive, false) {
+            Ok(result) => result,
+            Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, index, .. }) => {
+                EtcdResult{ action: String::new(), node: None, previous_node: None,
+                            x_etcd_index: index, x_raft_index: 0, x_raft_term: 0 }
+            },
+            Err(e) => return Err(e),
+        };
+
+        // resuming from index 1 would only be answered with another EventIndexCleared
+        let missing = EtcdError::MissingField{ field: "X-Etcd-Index".to_string(), key: Some(self.key.clone()) };
+        let index = try!(resume_index(&result).ok_or(missing));
+
+        result.action = RESYNC_ACTION.to_string();
+        self.wait_index = Some(index + 1);
+
+        return Ok(result);
+    }
+}
+
+impl<'a> Iterator for Watcher<'a> {
+    type Item = Result<EtcdResult, EtcdError>;
+
+    fn next(&mut self) -> Option<Result<EtcdResult, EtcdError>> {
+        loop {
+            if self.is_cancelled() {
+                return None;
+            }
+
+            match self.client.watch_until(&self.key, self.wait_index, self.recursive, self.cancel.as_ref()) {
+                Ok(result) => {
+                    if let Some(index) = result.modified_index() {
+                        self.wait_index = Some(index + 1);
+                    }
+
+                    return Some(Ok(result));
+                },
+                Err(_) if self.is_cancelled() => return None,
+                Err(EtcdError::Api{ code: EtcdErrorCode::EventIndexCleared, .. }) => return Some(self.resync()),
+                Err(EtcdError::WatchTimeout{ index }) => {
+                    // a watch from now resumes after the index it was started at, the changes since would be missed
+                    //  by another watch from now
+                    if self.wait_index.is_none() {
+                        self.wait_index = index.map(|index| index + 1);
+                    }
+
+                    debug!("watch on {} timed out, reconnecting from index {:?}", self.key, self.wait_index);
+                    continue;
+                },
+                Err(ref e) if is_timeout(e) => {
+                    debug!("watch on {} timed out, reconnecting: {:?}", self.key, e);
+                    continue;
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// WatchHandle runs a Watcher on a thread of its own, see EtcdClient::watch_async
+///
+/// The events are delivered over a channel, in order. The watch ends after the first error that the Watcher doesn't
+///  recover from, or once it's cancelled; the long poll in flight is aborted by closing its connection. Dropping the
+///  handle cancels the watch without waiting for the thread.
+pub struct WatchHandle {
+    events: Receiver<Result<EtcdResult, EtcdError>>,
+    cancel: CancelToken,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatchHandle {
+    pub fn new(client: EtcdClient, key: &str, wait_index: Option<u64>, recursive: bool) -> WatchHandle {
+        let (sender, events) = channel();
+        let cancel = CancelToken::new();
+        let watcher_cancel = cancel.clone();
+        let key = key.to_string();
+
+        let thread = thread::spawn(move || {
+            let mut watcher = Watcher::new(&client, &key, wait_index, recursive);
+            watcher.cancel = Some(watcher_cancel);
+
+            for event in watcher {
+                let failed = event.is_err();
+
+                // the handle was dropped
+                if sender.send(event).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        return WatchHandle{ events: events, cancel: cancel, thread: Some(thread) };
+    }
+
+    /// blocks until the next event, None once the watch has ended
+    pub fn recv(&self) -> Option<Result<EtcdResult, EtcdError>> {
+        return self.events.recv().ok();
+    }
+
+    /// the next event if there is one, without blocking. Err(Disconnected) once the watch has ended
+    pub fn try_recv(&self) -> Result<Result<EtcdResult, EtcdError>, TryRecvError> {
+        return self.events.try_recv();
+    }
+
+    /// the channel the events are delivered on
+    pub fn events(&self) -> &Receiver<Result<EtcdResult, EtcdError>> {
+        return &self.events;
+    }
+
+    /// stops the watch, closing its connection, and waits for the thread to end
+    pub fn cancel(mut self) {
+        self.cancel.cancel();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Iterator for WatchHandle {
+    type Item = Result<EtcdResult, EtcdError>;
+
+    fn next(&mut self) -> Option<Result<EtcdResult, EtcdError>> {
+        return self.recv();
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// the command with the event in its environment, the value is empty if the node has none, i.e. after a delete
+fn event_command(command: &[&str], event: &EtcdResult) -> Command {
+    let (key, value, modified_index) = match event.node {
+        Some(ref node) => (&node.key as &str, node.value.as_ref().map(|v| v as &str).unwrap_or(""), node.modified_index.to_string()),
+        None => ("", "", event.x_etcd_index.to_string()),
+    };
+
+    let mut cmd = Command::new(command[0]);
+    cmd.args(&command[1..])
+       .env("ETCD_WATCH_ACTION", &event.action)
+       .env("ETCD_WATCH_KEY", key)
+       .env("ETCD_WATCH_VALUE", value)
+       .env("ETCD_WATCH_MODIFIED_INDEX", modified_index);
+
+    return cmd;
+}
+
+/// the index the result was read at, the largest modifiedIndex in it if the X-Etcd-Index header was missing
+fn resume_index(result: &EtcdResult) -> Option<u64> {
+    if result.x_etcd_index > 0 {
+        return Some(result.x_etcd_index);
+    }
+
+    return match result.node.as_ref().map_or(0, max_modified_index) {
+        0 => None,
+        index => Some(index),
+    };
+}
+
+fn max_modified_index(node: &EtcdNode) -> u64 {
+    let children = node.nodes.iter().flat_map(|nodes| nodes.iter()).map(max_modified_index).max().unwrap_or(0);
+    return cmp::max(node.modified_index as u64, children);
+}
+
+/// true if the same watch may succeed later, i.e. a member couldn't be reached or answered with a server error
+fn is_transient(error: &EtcdError) -> bool {
+    return match *error {
+        EtcdError::HttpError(HttpError::HttpIoError(ref e)) | EtcdError::IOError(ref e) => !transport::is_tls_error(e),
+        EtcdError::Unsuccessful(status) | EtcdError::Cluster{ status, .. } => status.is_server_error(),
+        EtcdError::Api{ code: EtcdErrorCode::RaftInternal, .. } |
+        EtcdError::Api{ code: EtcdErrorCode::LeaderElect, .. } |
+        EtcdError::Api{ code: EtcdErrorCode::WatcherCleared, .. } => true,
+        _ => is_timeout(error),
+    };
+}
+
+/// true if the long poll was closed or timed out rather than failing, the watch can be reissued
+pub fn is_timeout(error: &EtcdError) -> bool {
+    let io_error: &io::Error = match *error {
+        EtcdError::WatchTimeout{ .. } => return true,
+        EtcdError::IOError(ref e) => e,
+        EtcdError::HttpError(HttpError::HttpIoError(ref e)) => e,
+        _ => return false,
+    };
+
+    return match io_error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock |
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted => true,
+        _ => false,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::status::StatusCode;
+  use std::io;
+  use super::{event_command, is_transient, resume_index};
+  use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+  use etcd::etcd_node::EtcdNode;
+  use etcd::etcd_result::EtcdResult;
+
+  fn event(action: &str, value: Option<&str>) -> EtcdResult {
+    let node = EtcdNode{ key: "/jobs/1".to_string(), created_index: 5, modified_index: 7, value: value.map(|v| v.to_string()),
+                         expiration: None, ttl: None, dir: false, nodes: None };
+
+    return EtcdResult{ action: action.to_string(), node: Some(node), previous_node: None,
+                       x_etcd_index: 7, x_raft_index: 0, x_raft_term: 0 };
+  }
+
+  fn run(event: &EtcdResult) -> String {
+    let script = "echo \"$ETCD_WATCH_ACTION $ETCD_WATCH_KEY [$ETCD_WATCH_VALUE] $ETCD_WATCH_MODIFIED_INDEX\"";
+    let output = event_command(&["sh", "-c", script], event).output().unwrap();
+
+    assert!(output.status.success());
+    return String::from_utf8(output.stdout).unwrap();
+  }
+
+  #[test]
+  fn resume_index_test() {
+    let mut result = event("get", Some("run"));
+    assert_eq!(resume_index(&result), Some(7));
+
+    // without the header the largest index read is used
+    result.x_etcd_index = 0;
+    result.node.as_mut().unwrap().modified_index = 5;
+    assert_eq!(resume_index(&result), Some(5));
+
+    result.node = None;
+    assert_eq!(resume_index(&result), None);
+  }
+
+  #[test]
+  fn transient_test() {
+    assert!(is_transient(&EtcdError::IOError(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"))));
+    assert!(is_transient(&EtcdError::Unsuccessful(StatusCode::ServiceUnavailable)));
+    assert!(is_transient(&EtcdError::Api{ code: EtcdErrorCode::LeaderElect, message: String::new(), cause: None, index: 0 }));
+
+    assert!(!is_transient(&EtcdError::Unsuccessful(StatusCode::Forbidden)));
+    assert!(!is_transient(&EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, message: String::new(), cause: None, index: 0 }));
+    assert!(!is_transient(&EtcdError::MissingField{ field: "node".to_string(), key: None }));
+  }
+
+  #[test]
+  fn event_command_test() {
+    assert_eq!(run(&event("set", Some("run"))), "set /jobs/1 [run] 7\n");
+    assert_eq!(run(&event("delete", None)), "delete /jobs/1 [] 7\n");
+  }
+}
@@ -1,48 +1,81 @@
-static X_ETCD_INDEX: &'static str = "X-Etcd-Index";
-static X_RAFT_INDEX: &'static str = "X-Raft-Index";
-static X_RAFT_TERM:  &'static str = "X-Raft-Term";
-
-pub struct X_Etcd_Index(u32);
-pub struct X_Raft_Index(u32);
-pub struct X_Raft_Term(u32);
-
+use hyper::header::{Header, HeaderFormat};
+use hyper::header::parsing::from_one_raw_str;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
 
 macro_rules! create_header {
+  ($(#[$doc:meta])* struct $struct_name:ident => $header_name:expr) => (
 
-($struct_name:ty, $stat_str:ident) => (
+  $(#[$doc])*
+  #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+  pub struct $struct_name(pub u64);
 
   impl Deref for $struct_name {
-    fn deref<'a>(&'a self) -> &'a u32 {
-	  let (i) = self;
-        return i;
+    type Target = u64;
+
+    fn deref<'a>(&'a self) -> &'a u64 {
+      return &self.0;
     }
   }
 
   impl Header for $struct_name {
-    fn header_name() -> &' static str {
-      return $stat_str;
-	}
+    fn header_name() -> &'static str {
+      return $header_name;
+    }
 
     fn parse_header(raw: &[Vec<u8>]) -> Option<$struct_name> {
-      let num: Option<u32> = from_str(raw);
-	  if let Some(i) = num {
-	    return Some($struct_name(i));
-	  }
-
-	  return None;
+      return from_one_raw_str(raw).map(|i| $struct_name(i));
     }
   }
 
   impl HeaderFormat for $struct_name {
-    fn fmt_header(& self, fmt: &mut Formatter) -> Result {
-	  write!(f, "{}", *self)
-	}
+    fn fmt_header(&self, f: &mut Formatter) -> fmt::Result {
+      return Display::fmt(&self.0, f);
+    }
   }
 
   impl Display for $struct_name {
-    fn fmt(& self, f: &mut Formatter) -> Result {
-	  write!(f, "{}", *self)
-	}
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+      return Display::fmt(&self.0, f);
+    }
+  }
+  )
+}
+
+create_header!(
+  /// X-Etcd-Index: the current etcd index, i.e. the index of the last change to the store
+  struct XEtcdIndex => "X-Etcd-Index"
+);
+
+create_header!(
+  /// X-Raft-Index: similar to the etcd index but for the underlying raft protocol
+  struct XRaftIndex => "X-Raft-Index"
+);
+
+create_header!(
+  /// X-Raft-Term: increases whenever a master election happens in the cluster
+  struct XRaftTerm => "X-Raft-Term"
+);
+
+#[cfg(test)]
+mod tests {
+  use hyper::header::{Header, Headers};
+  use super::{XEtcdIndex, XRaftIndex, XRaftTerm};
+
+  #[test]
+  fn parse_header_test() {
+    assert_eq!(XEtcdIndex::parse_header(&[b"42".to_vec()]), Some(XEtcdIndex(42)));
+    assert_eq!(XRaftIndex::parse_header(&[b"not a number".to_vec()]), None);
+    assert_eq!(XRaftTerm::parse_header(&[b"1".to_vec(), b"2".to_vec()]), None);
+  }
+
+  #[test]
+  fn headers_test() {
+    let mut headers = Headers::new();
+    headers.set_raw("X-Etcd-Index", vec![b"7".to_vec()]);
+
+    assert_eq!(**headers.get::<XEtcdIndex>().unwrap(), 7);
+    assert!(headers.get::<XRaftTerm>().is_none());
   }
-)
-}
\ No newline at end of file
+}
@@ -1,32 +1,64 @@
-mod etcd_error;
-mod etcd_node;
-mod etcd_result;
+pub mod backup;
+pub mod etcd_error;
+pub mod etcd_health;
+pub mod etcd_member;
+pub mod etcd_node;
+pub mod etcd_result;
+pub mod etcd_stats;
+pub mod etcd_version;
+pub mod etcd_watcher;
+pub mod header;
+pub mod retry;
+pub mod transport;
+mod decode;
+mod etcd_client_builder;
+mod etcd_tls;
 
 #[cfg(test)]
 mod tests;
 
-use hyper::client::{Body,Client};
-use hyper::mime::{Mime, TopLevel, SubLevel};
-use hyper::header;
+use hyper::error::HttpError;
+use hyper::method::Method;
 use hyper;
-use hyper::Url;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use etcd::backup::{BackupSummary, RestorePolicy, RestoreSummary};
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_health::{ClusterHealth, MemberCheck, MemberHealth};
+use etcd::etcd_member::Member;
 use etcd::etcd_node::EtcdNode;
-use etcd::etcd_result::EtcdResult;
+use etcd::etcd_result::{CompareResult, EtcdResult};
+use etcd::etcd_stats::{LeaderStats, SelfStats, StoreStats};
+use etcd::etcd_version::{Capabilities, EtcdVersion, Feature};
+use etcd::etcd_watcher::{WatchHandle, Watcher};
+use etcd::header::{XEtcdIndex, XRaftIndex, XRaftTerm};
+use etcd::retry::RetryPolicy;
+use etcd::transport::{CancelToken, RequestBody, Transport, TransportRequest, TransportResponse};
 use rustc_serialize::json;
 use url;
 
+pub use self::etcd_client_builder::EtcdClientBuilder;
+
 // etcd protocol version
 static VERSION: &'static str = "v2";
 
+// how long a gated request goes without asking for the version again after it couldn't be asked for
+static RENEGOTIATE_SECS: u64 = 30;
+
 
 /// EtcdObject, i.e. the base Etcd path
+#[derive(Clone, Copy)]
 enum EtcdObject {
    Version,
    Keys,
+   Members,
    Stats,
 }
 
@@ -35,6 +67,7 @@ impl Display for EtcdObject {
 	let object_str = match *self {
 			EtcdObject::Version => "version",
 			EtcdObject::Keys => "keys",
+			EtcdObject::Members => "members",
 			EtcdObject::Stats => "stats",
 		};
 
@@ -44,7 +77,9 @@ impl Display for EtcdObject {
   }
 }
 
-enum AtomicOp<'a> {
+/// The conditions for compare_and_swap and compare_and_delete, all of them must hold for the operation to succeed.
+#[derive(Clone, Copy, Debug)]
+pub enum AtomicOp<'a> {
    /// The PrevValue must match the specified value.
    PrevValue(&'a str),
    /// The PrevIndex must match the specified index.
@@ -59,7 +94,7 @@ impl<'a> AtomicOp<'a> {
 	   match self {
 		      AtomicOp::PrevValue(s) => ("prevValue".into(), s.into()),
 		      AtomicOp::PrevIndex(i) => ("prevIndex".into(), i.to_string()),
-		      AtomicOp::PrevExist(b) => ("prevExists".into(), b.to_string()),
+		      AtomicOp::PrevExist(b) => ("prevExist".into(), b.to_string()),
 		   }
    }
 }
@@ -67,7 +102,9 @@ impl<'a> AtomicOp<'a> {
 enum Param<'a> {
    Dir(bool),
    Recursive(bool),
+   Refresh(bool),
    Sorted(bool),
+   Ttl(u64),
    Value(&'a str),
    Wait(bool),
    WaitIndex(u64),
@@ -78,7 +115,9 @@ impl<'a> Param<'a> {
 		match self {
 				Param::Dir(b) => ("dir".into(), b.to_string()),
 				Param::Recursive(b) => ("recursive".into(), b.to_string()),
+				Param::Refresh(b) => ("refresh".into(), b.to_string()),
                 Param::Sorted(b) => ("sorted".into(), b.to_string()),
+				Param::Ttl(i) => ("ttl".into(), i.to_string()),
 			    Param::Value(s) => ("value".into(), s.into()),
                 Param::Wait(b) => ("wait".into(), b.to_string()),
 			    Param::WaitIndex(i) => ("waitIndex".into(), i.to_string()),
@@ -88,191 +127,652 @@ impl<'a> Param<'a> {
 
 
 
-/// EtcdClient for requesting
+/// what the client knows of the features of the cluster
+#[derive(Clone)]
+enum Negotiation {
+    /// the version hasn't been asked for yet
+    Unknown,
+    Negotiated(Capabilities),
+    /// the version couldn't be asked for at the instant
+    Failed(Instant),
+}
+
+/// EtcdClient for requesting, construct with the EtcdClientBuilder
+///
+/// Clones share the connections, and the member requests are sent to.
+#[derive(Clone)]
 pub struct EtcdClient {
-    etcd_host: String,
-    etcd_port: u16,
+    /// the base urls of the cluster members, i.e. http://host:port
+    endpoints: Vec<String>,
+    /// the index into endpoints of the member which last answered successfully
+    current: Arc<AtomicUsize>,
+    /// sends the requests, hyper unless another was set on the builder
+    transport: Arc<Box<Transport>>,
+    /// how idempotent requests are retried after transient failures
+    retry: RetryPolicy,
+    /// the features of the cluster, asked for when the client is built or the first time a feature which needs them is used
+    negotiation: Arc<Mutex<Negotiation>>,
 }
 
 impl EtcdClient {
-    fn build_url<'a>(&self, object: EtcdObject, path: &str, params: &'a Vec<(String,String)>) -> hyper::Url {
-		// todo this should be https
-		let mut url = hyper::Url::parse(&format!("http://{h}:{p}/{v}/{o}/{pt}",
-                                                h = self.etcd_host, p = self.etcd_port,
-                                                v = VERSION, o = object, pt = path));
-        if let Err(e) = url {
-            panic!("error parsing url: {}", e);
-        }
+    fn new(endpoints: Vec<String>, transport: Box<Transport>, retry: RetryPolicy) -> EtcdClient {
+        return EtcdClient{ endpoints: endpoints, current: Arc::new(AtomicUsize::new(0)), transport: Arc::new(transport), retry: retry,
+                           negotiation: Arc::new(Mutex::new(Negotiation::Unknown)) };
+    }
 
-        let mut url = url.unwrap();
-        for i in params.iter().map(|&(ref k,ref v)| -> (&'a str, &'a str) { (k,v) }) {
-            println!("query: {:?}", i);
+    /// the features of the cluster, the version is asked for until it has been answered and then kept
+    pub fn capabilities(&self) -> Result<Capabilities, EtcdError> {
+        if let Negotiation::Negotiated(ref capabilities) = *self.negotiation.lock().unwrap() {
+            return Ok(capabilities.clone());
         }
+
+        return self.negotiate();
+    }
+
+    /// asks the cluster for its version, the lock isn't held during the request so other calls don't wait behind it
+    fn negotiate(&self) -> Result<Capabilities, EtcdError> {
+        let version = match self.version() {
+            Ok(version) => version,
+            Err(e) => {
+                let mut negotiation = self.negotiation.lock().unwrap();
+
+                // another call may have been answered in the meantime
+                if let Negotiation::Negotiated(ref capabilities) = *negotiation {
+                    return Ok(capabilities.clone());
+                }
+
+                *negotiation = Negotiation::Failed(Instant::now());
+                return Err(e);
+            },
+        };
+
+        info!("etcd cluster is at version {}", version.effective());
+
+        let negotiated = Capabilities::new(version);
+        *self.negotiation.lock().unwrap() = Negotiation::Negotiated(negotiated.clone());
+        return Ok(negotiated);
+    }
+
+    /// Unsupported if the cluster doesn't support the feature
+    ///  if the version can't be asked for, the request is sent anyway and fails or succeeds on its own, and the version
+    ///  isn't asked for again until RENEGOTIATE_SECS have passed.
+    fn require(&self, feature: Feature) -> Result<(), EtcdError> {
+        let negotiation = self.negotiation.lock().unwrap().clone();
+        let capabilities = match negotiation {
+            Negotiation::Negotiated(capabilities) => capabilities,
+            Negotiation::Failed(at) if at.elapsed() < Duration::from_secs(RENEGOTIATE_SECS) => return Ok(()),
+            _ => match self.negotiate() {
+                Ok(capabilities) => capabilities,
+                Err(e) => {
+                    warn!("could not ask the etcd cluster for its version, not asking again for {}s: {:?}", RENEGOTIATE_SECS, e);
+                    return Ok(());
+                },
+            },
+        };
+
+        return capabilities.require(feature);
+    }
+
+    /// the list of members this client will use, in order of failover
+    pub fn endpoints(&self) -> &[String] {
+        return &self.endpoints;
+    }
+
+    fn build_url<'a>(endpoint: &str, object: EtcdObject, path: &str, params: &'a Vec<(String,String)>) -> Result<hyper::Url, EtcdError> {
+        let url_str = match object {
+            // the version isn't part of the versioned api
+            EtcdObject::Version => format!("{e}/{o}", e = endpoint, o = object),
+            _ => format!("{e}/{v}/{o}/{pt}", e = endpoint, v = VERSION, o = object, pt = path),
+        };
+
+        let mut url = try!(hyper::Url::parse(&url_str));
+
         url.set_query_from_pairs(params.iter().map(|&(ref k,ref v)| -> (&'a str, &'a str) { (k,v) }));
-        println!("url: {:?}", url);
+        debug!("url: {:?}", url);
 
-        return url;
+        return Ok(url);
     }
 
-	#[inline(always)]
-	fn accept_json_header() -> hyper::header::Accept {
-		return hyper::header::Accept(vec![header::qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]);
-	}
+    /// sends the request to the current member, failing over to each of the other members in turn if the
+    ///  connection to it fails. The member which answers becomes the current member for subsequent requests.
+    fn send(&self, method: Method, object: EtcdObject, path: &str, params: &Vec<(String,String)>, body: Option<&str>) -> Result<TransportResponse, EtcdError> {
+        return self.send_request(method, object, path, params, body.map(RequestBody::Form), false, None);
+    }
+
+    /// sends a request with a json body, see send()
+    fn send_json(&self, method: Method, object: EtcdObject, path: &str, body: &str) -> Result<TransportResponse, EtcdError> {
+        return self.send_request(method, object, path, &vec![], Some(RequestBody::Json(body)), false, None);
+    }
+
+    /// sends a watch, see send()
+    fn send_watch(&self, path: &str, params: &Vec<(String,String)>, cancel: Option<&CancelToken>) -> Result<TransportResponse, EtcdError> {
+        return self.send_request(Method::Get, EtcdObject::Keys, path, params, None, true, cancel);
+    }
 
-    #[inline(always)]
-	fn content_type_form_header() -> hyper::header::ContentType {
-	    return hyper::header::ContentType(Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, vec![]))
+    /// sends the request, retrying it according to the RetryPolicy if it's idempotent
+    fn send_request(&self, method: Method, object: EtcdObject, path: &str, params: &Vec<(String,String)>,
+                    body: Option<RequestBody>, long_poll: bool, cancel: Option<&CancelToken>) -> Result<TransportResponse, EtcdError> {
+        // a write conditioned on the index can only be applied once. If the response to the first attempt is lost,
+        //  the retry fails the comparison as the index has moved on, see compare_and_swap()
+        let idempotent = method == Method::Get || params.iter().any(|&(ref k, _)| k == "prevIndex");
+
+        let mut retry = 0;
+        loop {
+            let result = self.send_to_members(method.clone(), object, path, params, body, long_poll, idempotent, cancel);
+            if !idempotent || retry >= self.retry.max_retries || !EtcdClient::is_transient(&result) {
+                return result;
+            }
+
+            if cancel.map_or(false, |c| c.is_cancelled()) {
+                return result;
+            }
+
+            let backoff = self.retry.backoff(retry);
+            warn!("retrying {} {} in {:?} after a transient failure", method, path, backoff);
+            thread::sleep(backoff);
+            retry += 1;
+        }
     }
 
-	fn to_etcd_result(mut response: hyper::client::response::Response) -> Result<EtcdResult, etcd_error::EtcdError> {
+    /// true if the same request may succeed later
+    fn is_transient(result: &Result<TransportResponse, EtcdError>) -> bool {
+        return match *result {
+            Ok(ref response) => response.status.is_server_error(),
+            Err(EtcdError::HttpError(HttpError::HttpIoError(_))) => true,
+            Err(EtcdError::IOError(ref e)) => e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock,
+            Err(_) => false,
+        };
+    }
+
+    /// sends the request to each of the members in turn, see send(). A request which isn't idempotent is only sent to
+    ///  the next member if the connection failed, i.e. it was refused or timed out, otherwise it may already have been
+    ///  applied.
+    fn send_to_members(&self, method: Method, object: EtcdObject, path: &str, params: &Vec<(String,String)>,
+                       body: Option<RequestBody>, long_poll: bool, idempotent: bool, cancel: Option<&CancelToken>) -> Result<TransportResponse, EtcdError> {
+        let start = self.current.load(Ordering::Relaxed);
+        let mut last_error: EtcdError = EtcdError::NoEndpoints;
+
+        for attempt in 0..self.endpoints.len() {
+            let index = (start + attempt) % self.endpoints.len();
+            let url = try!(EtcdClient::build_url(&self.endpoints[index], object, path, params));
+
+            let request = TransportRequest{ method: method.clone(), url: &url, body: body, long_poll: long_poll, cancel: cancel };
+
+            match self.transport.send(&request) {
+                Ok(response) => {
+                    if index != start {
+                        info!("failed over to etcd member: {}", self.endpoints[index]);
+                        self.current.store(index, Ordering::Relaxed);
+                    }
+
+                    return Ok(response);
+                },
+                Err(EtcdError::HttpError(HttpError::HttpIoError(e))) => {
+                    let cancelled = cancel.map_or(false, |c| c.is_cancelled());
+                    if cancelled || (!idempotent && !transport::is_connect_error(&e)) {
+                        return Err(EtcdError::from(HttpError::HttpIoError(e)));
+                    }
+
+                    warn!("etcd member {} is unreachable: {}", self.endpoints[index], e);
+                    last_error = EtcdError::from(HttpError::HttpIoError(e));
+                },
+                Err(e) => return Err(e),
+            }
+        }
+
+        return Err(last_error);
+    }
+
+    /// adds the ttl to the form if there is one, without a ttl the key will not expire
+    fn with_ttl(mut form: Vec<(String,String)>, ttl: Option<u64>) -> Vec<(String,String)> {
+        if let Some(ttl) = ttl {
+            form.push(Param::Ttl(ttl).into());
+        }
+
+        return form;
+    }
+
+    fn to_etcd_result(response: TransportResponse) -> Result<EtcdResult, EtcdError> {
 		if !response.status.is_success() {
-			// todo should log here.
-			return Err(etcd_error::EtcdError::Unsuccessful(response.status));
+            let error = EtcdError::from_response(response.status, &response.body);
+            debug!("etcd returned {}: {:?}", response.status, error);
+            return Err(error);
 		}
 
-		let result_object = try!(json::Json::from_reader(&mut response));
-		assert!(result_object.is_object(), "expected the result object here");
+        let result_json = try!(json::Json::from_str(&response.body));
+        let result_object = try!(decode::as_object(&result_json, "result", None));
+        let mut result = try!(EtcdResult::from_json(result_object));
 
-		let result_object = result_object.as_object().unwrap();
-		return Ok(EtcdResult::from_json(result_object));
+        result.x_etcd_index = response.headers.get::<XEtcdIndex>().map_or(0, |h| **h);
+        result.x_raft_index = response.headers.get::<XRaftIndex>().map_or(0, |h| **h);
+        result.x_raft_term = response.headers.get::<XRaftTerm>().map_or(0, |h| **h);
 
+        return Ok(result);
 	}
 
-	// backup		backup an etcd directory
-    // cluster-health	check the health of the etcd cluster
+    /// write the directory and everything beneath it to the writer, see the backup module for the format
+    ///  the keys are read in a single request, so the backup is a consistent snapshot at the index it returns. A key
+    ///  which is a file fails with NotDir.
+    pub fn backup<W: Write>(&self, dir: &str, writer: &mut W) -> Result<BackupSummary, EtcdError> {
+        return backup::backup(self, dir, writer);
+    }
+
+    /// recreate a backup beneath the target directory, which may be on another cluster
+    ///  the policy decides what is done with keys which already exist, and can rename the keys restored.
+    pub fn restore<R: Read>(&self, reader: &mut R, target_dir: &str, policy: &RestorePolicy) -> Result<RestoreSummary, EtcdError> {
+        return backup::restore(self, reader, target_dir, policy);
+    }
+
+    /// the versions of the member which answered and of the cluster
+    pub fn version(&self) -> Result<EtcdVersion, EtcdError> {
+        let response = try!(EtcdClient::to_cluster_result(self.send(Method::Get, EtcdObject::Version, "", &vec![], None)));
+        return EtcdVersion::from_body(&response.body);
+    }
+
+    /// check the health of each member of the cluster, and of the cluster as a whole
+    ///  the members are listed by the cluster, so members missing from the endpoints are checked too. A member which
+    ///  can't be reached is reported as unhealthy rather than failing the check.
+    pub fn cluster_health(&self) -> Result<ClusterHealth, EtcdError> {
+        try!(self.require(Feature::Health));
+        let members = try!(self.members_list());
+        let health: Vec<MemberHealth> = members.into_iter().map(|member| self.member_health(member)).collect();
+
+        return Ok(ClusterHealth::new(health));
+    }
+
+    /// list the members of the cluster
+    pub fn members_list(&self) -> Result<Vec<Member>, EtcdError> {
+        try!(self.require(Feature::Members));
+        let response = try!(EtcdClient::to_cluster_result(self.send(Method::Get, EtcdObject::Members, "", &vec![], None)));
+        let members_json = try!(json::Json::from_str(&response.body));
+
+        return Member::list_from_json(try!(decode::as_object(&members_json, "members", None)));
+    }
+
+    /// add a member to the cluster, the new member must then be started with the cluster as its initial cluster
+    ///  returns the new member, which has no name or client urls until it has started.
+    pub fn member_add(&self, peer_urls: &[&str]) -> Result<Member, EtcdError> {
+        try!(self.require(Feature::Members));
+        let body = EtcdClient::peer_urls_json(peer_urls);
+        let response = try!(EtcdClient::to_cluster_result(self.send_json(Method::Post, EtcdObject::Members, "", &body)));
+        let member_json = try!(json::Json::from_str(&response.body));
+
+        return Member::from_json(try!(decode::as_object(&member_json, "member", None)));
+    }
+
+    /// remove the member from the cluster, the member stops itself once it has been removed
+    ///  fails with a Cluster error if there is no member with the id.
+    pub fn member_remove(&self, id: &str) -> Result<(), EtcdError> {
+        try!(self.require(Feature::Members));
+        try!(EtcdClient::to_cluster_result(self.send(Method::Delete, EtcdObject::Members, id, &vec![], None)));
+        return Ok(());
+    }
+
+    /// change the peer urls of the member, i.e. after it moved to another host
+    ///  fails with a Cluster error if there is no member with the id.
+    pub fn member_update(&self, id: &str, peer_urls: &[&str]) -> Result<(), EtcdError> {
+        try!(self.require(Feature::Members));
+        let body = EtcdClient::peer_urls_json(peer_urls);
+        try!(EtcdClient::to_cluster_result(self.send_json(Method::Put, EtcdObject::Members, id, &body)));
+        return Ok(());
+    }
+
+    /// {"peerURLs":[..]}
+    fn peer_urls_json(peer_urls: &[&str]) -> String {
+        let mut obj = BTreeMap::new();
+        obj.insert("peerURLs".to_string(), json::Json::Array(peer_urls.iter().map(|url| json::Json::String(url.to_string())).collect()));
+
+        return json::Json::Object(obj).to_string();
+    }
+
+    /// the stats of the replication from the leader to each of the followers
+    ///  only the leader has these, a follower answers with a Cluster error.
+    pub fn leader_stats(&self) -> Result<LeaderStats, EtcdError> {
+        let response = try!(EtcdClient::to_cluster_result(self.send(Method::Get, EtcdObject::Stats, "leader", &vec![], None)));
+        return EtcdClient::decode_stats(&response, LeaderStats::from_json);
+    }
+
+    /// the stats of the member which answered, see endpoints() for the order members are tried in
+    pub fn self_stats(&self) -> Result<SelfStats, EtcdError> {
+        let response = try!(EtcdClient::to_cluster_result(self.send(Method::Get, EtcdObject::Stats, "self", &vec![], None)));
+        return EtcdClient::decode_stats(&response, SelfStats::from_json);
+    }
+
+    /// the counts of the operations on the store of the member which answered
+    pub fn store_stats(&self) -> Result<StoreStats, EtcdError> {
+        let response = try!(EtcdClient::to_cluster_result(self.send(Method::Get, EtcdObject::Stats, "store", &vec![], None)));
+        return EtcdClient::decode_stats(&response, StoreStats::from_json);
+    }
+
+    fn decode_stats<T, F>(response: &TransportResponse, from_json: F) -> Result<T, EtcdError>
+    where F: Fn(&json::Object) -> Result<T, EtcdError> {
+        let stats_json = try!(json::Json::from_str(&response.body));
+        return from_json(try!(decode::as_object(&stats_json, "stats", None)));
+    }
+
+    /// the cluster apis answer with an error status and a message rather than an etcd error
+    fn to_cluster_result(response: Result<TransportResponse, EtcdError>) -> Result<TransportResponse, EtcdError> {
+        let response = try!(response);
+        if !response.status.is_success() {
+            return Err(EtcdError::from_response(response.status, &response.body));
+        }
+
+        return Ok(response);
+    }
+
+    /// checks the member on each of its client urls until one answers
+    fn member_health(&self, member: Member) -> MemberHealth {
+        let client_urls = member.client_urls.clone();
+        let mut health = MemberHealth::new(member);
+
+        for client_url in client_urls {
+            // each client url starts over, so nothing is left over from a url which failed part way
+            health = MemberHealth::new(health.member);
+
+            match self.check_member(&client_url, &mut health) {
+                Ok(()) => break,
+                Err((check, e)) => {
+                    warn!("could not check the health of etcd member {} on {}{}: {:?}", health.member.id, client_url, check, e);
+                    health.error = Some(e);
+                    health.failed_check = Some(check);
+                },
+            }
+        }
+
+        return health;
+    }
+
+    /// sets the fields of each check only once it succeeded, the error names the check which failed
+    fn check_member(&self, client_url: &str, health: &mut MemberHealth) -> Result<(), (MemberCheck, EtcdError)> {
+        let start = Instant::now();
+        let healthy = try!(self.check_health(client_url).map_err(|e| (MemberCheck::Health, e)));
+        health.latency = Some(start.elapsed());
+        health.reachable = true;
+        health.healthy = healthy;
+
+        let stats = try!(EtcdClient::to_cluster_result(self.send_to_member(client_url, &format!("{}/{}/self", VERSION, EtcdObject::Stats)))
+                         .and_then(|response| EtcdClient::decode_stats(&response, SelfStats::from_json))
+                         .map_err(|e| (MemberCheck::Stats, e)));
+        health.leader = stats.is_leader();
+
+        // only the keys api reports the term
+        let response = try!(self.send_to_member(client_url, &format!("{}/{}/", VERSION, EtcdObject::Keys)).map_err(|e| (MemberCheck::Keys, e)));
+        health.raft_term = response.headers.get::<XRaftTerm>().map(|h| **h);
+
+        return Ok(());
+    }
+
+    fn check_health(&self, client_url: &str) -> Result<bool, EtcdError> {
+        let response = try!(self.send_to_member(client_url, "health"));
+
+        // an unhealthy member answers {"health": "false"}, with an error status on some versions
+        let health_json = try!(json::Json::from_str(&response.body));
+        let health_object = try!(decode::as_object(&health_json, "health", None));
+        return Ok(try!(decode::as_string(try!(decode::required(health_object, "health", None)), "health", None)) == "true");
+    }
+
+    /// sends a GET to the member itself, without any failover or retries, path is relative to the client url
+    fn send_to_member(&self, client_url: &str, path: &str) -> Result<TransportResponse, EtcdError> {
+        let url = try!(hyper::Url::parse(&format!("{}/{}", client_url.trim_right_matches('/'), path)));
+        let request = TransportRequest{ method: Method::Get, url: &url, body: None, long_poll: false, cancel: None };
+
+        return self.transport.send(&request);
+    }
 
     /// make an index value from the specified key (directory) with an ever increasing ordered index.
-    fn index_append(&self, key: &str, value: &str) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
-        // TODO this seems like a queue, should we offer queue style operations?
-		let url = self.build_url(EtcdObject::Keys, key, &vec![]);
-		let body = url::form_urlencoded::serialize_owned(&vec![Param::Value(value).into()]);
-		let response: hyper::client::response::Response = try!(Client::new()
-															   .post(url)
-															   .body(&body as &str)
-															   .header(Self::accept_json_header())
-                                                               .header(Self::content_type_form_header())
-                                                               .send());
+    pub fn index_append(&self, key: &str, value: &str) -> Result<Option<EtcdNode>, EtcdError> {
+        // see recipes::queue::Queue for queue operations on the directory
+        return self.create_in_order(key, value, None);
+    }
+
+    /// create a new key in the directory, named with an ever increasing ordered index
+    ///  the key is removed after ttl seconds, if specified.
+    ///  returns the new node.
+    pub fn create_in_order(&self, dir: &str, value: &str, ttl: Option<u64>) -> Result<Option<EtcdNode>, EtcdError> {
+        let body = url::form_urlencoded::serialize_owned(&EtcdClient::with_ttl(vec![Param::Value(value).into()], ttl));
+        let response = try!(self.send(Method::Post, EtcdObject::Keys, dir, &vec![], Some(&body)));
 		let result = try!(EtcdClient::to_etcd_result(response));
 
 		return Ok(result.node);
 	}
 
     /// this will return the ordered set of indexes on the specified keys
-    fn index_list(&self, key: &str) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
-        let url = self.build_url(EtcdObject::Keys, key, &vec![Param::Recursive(true).into(), Param::Sorted(true).into()]);
-		let response: hyper::client::response::Response = try!(Client::new()
-															   .get(url)
-															   .header(EtcdClient::accept_json_header())
-															   .send());
+    pub fn index_list(&self, key: &str) -> Result<Option<EtcdNode>, EtcdError> {
+        let params = vec![Param::Recursive(true).into(), Param::Sorted(true).into()];
+        let response = try!(self.send(Method::Get, EtcdObject::Keys, key, &params, None));
 		let result = try!(EtcdClient::to_etcd_result(response));
 
 		return Ok(result.node);
 	}
 
-
     /// make a new directory
-	fn make_dir(&self, name: &str) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
-		let url = self.build_url(EtcdObject::Keys, name, &vec![]);
-		let body = url::form_urlencoded::serialize_owned(&vec![Param::Dir(true).into()]);
-        let response: hyper::client::response::Response = try!(Client::new()
-															   .put(url)
-															   .body(&body as &str)
-                                                               .header(Self::accept_json_header())
-															   .header(Self::content_type_form_header())
-															   .send());
-
+    ///  the directory and everything in it is removed after ttl seconds, if specified.
+    pub fn make_dir(&self, name: &str, ttl: Option<u64>) -> Result<Option<EtcdNode>, EtcdError> {
+        let body = url::form_urlencoded::serialize_owned(&EtcdClient::with_ttl(vec![Param::Dir(true).into()], ttl));
+        let response = try!(self.send(Method::Put, EtcdObject::Keys, name, &vec![], Some(&body)));
 		let result = try!(EtcdClient::to_etcd_result(response));
+
 		return Ok(result.node);
 	}
 
     /// remove a key
 	///  returns the node if it existsed.
-	fn remove(&self, key: &str) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
-		let url = self.build_url(EtcdObject::Keys, key, &vec![]);
-		let response: hyper::client::response::Response = try!(Client::new()
-															   .delete(url)
-															   .header(EtcdClient::accept_json_header())
-															   .send());
+    pub fn remove(&self, key: &str) -> Result<Option<EtcdNode>, EtcdError> {
+        let response = try!(self.send(Method::Delete, EtcdObject::Keys, key, &vec![], None));
 		let result = try!(EtcdClient::to_etcd_result(response));
+
 		return Ok(result.previous_node);
 	}
 
     //// removes the key if it is an empty directory or a key-value pair
-	fn remove_dir(&self, dir: &str, recursive: bool) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
-		let url = self.build_url(EtcdObject::Keys, dir, &vec![Param::Dir(true).into(), Param::Recursive(recursive).into()]);
-		let response: hyper::client::response::Response = try!(Client::new()
-															   .delete(url)
-															   .header(EtcdClient::accept_json_header())
-															   .send());
+    pub fn remove_dir(&self, dir: &str, recursive: bool) -> Result<Option<EtcdNode>, EtcdError> {
+        let params = vec![Param::Dir(true).into(), Param::Recursive(recursive).into()];
+        let response = try!(self.send(Method::Delete, EtcdObject::Keys, dir, &params, None));
 		let result = try!(EtcdClient::to_etcd_result(response));
+
 		return Ok(result.previous_node)
 	}
 
     /// retrieve the value of a key
-	fn get(&self, key: &str) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
-		println!("getting {}", key);
-		let url = self.build_url(EtcdObject::Keys, key, &vec![]);
-
-		let response: hyper::client::response::Response = try!(Client::new()
-															   .get(url)
-															   .header(EtcdClient::accept_json_header())
-															   .send());
-		let result = try!(EtcdClient::to_etcd_result(response));
-
-		println!("result {:?}", result);
+    pub fn get(&self, key: &str) -> Result<Option<EtcdNode>, EtcdError> {
+        debug!("getting {}", key);
+        let result = try!(self.get_result(key, false, false));
 
+        debug!("result {:?}", result);
 		return Ok(result.node);
 	}
 
+    /// retrieve the key, and all the keys beneath it if recursive, along with the index it was read at
+    ///  to watch for changes from, the keys of each directory are in order if sorted.
+    pub fn get_result(&self, key: &str, recursive: bool, sorted: bool) -> Result<EtcdResult, EtcdError> {
+        let mut params = vec![];
+        if recursive {
+            params.push(Param::Recursive(true).into());
+        }
+
+        if sorted {
+            params.push(Param::Sorted(true).into());
+        }
+
+        let response = try!(self.send(Method::Get, EtcdObject::Keys, key, &params, None));
+        return EtcdClient::to_etcd_result(response);
+    }
+
     //// retrieve a directory, this is just a wrapper for get...
-	fn list<'a>(&self, dir: &'a str) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
+    pub fn list<'a>(&self, dir: &'a str) -> Result<Option<EtcdNode>, EtcdError> {
         return self.get(dir);
     }
 
     /// set the value of a key
+    ///  the key is removed after ttl seconds, if specified.
 	///  returns the previous node if there was one.
-	fn set<'a>(&self, key: &'a str, value: &'a str) -> Result<Option<EtcdNode>, etcd_error::EtcdError> {
-		let url = self.build_url(EtcdObject::Keys, key, &vec![Param::Dir(false).into()]);
-
-		let body = url::form_urlencoded::serialize_owned(&vec![Param::Value(value).into()]/*.map(|x| map_str_string(x))*/);
-		let response: hyper::client::response::Response = try!(Client::new()
-															   .put(url)
-															   .body(&body as &str)
-															   .header(hyper::header::ContentType(Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, vec![])))
-															   .header(EtcdClient::accept_json_header())
-															   .send());
-		let result =  try!(EtcdClient::to_etcd_result(response));
+    pub fn set<'a>(&self, key: &'a str, value: &'a str, ttl: Option<u64>) -> Result<Option<EtcdNode>, EtcdError> {
+        let body = url::form_urlencoded::serialize_owned(&EtcdClient::with_ttl(vec![Param::Value(value).into()], ttl));
+        let response = try!(self.send(Method::Put, EtcdObject::Keys, key, &vec![Param::Dir(false).into()], Some(&body)));
+        let result = try!(EtcdClient::to_etcd_result(response));
+
 		return Ok(result.previous_node);
 	}
 
-    //// create a new or existing directory ???
-	//fn set_dir(dir: &str) {}
+    /// reset the ttl of an existing key or directory without changing its value
+    ///  watchers are not notified of the refresh, fails with KeyNotFound if the key does not exist.
+    ///  returns the refreshed node.
+    pub fn refresh(&self, key: &str, ttl: u64) -> Result<Option<EtcdNode>, EtcdError> {
+        try!(self.require(Feature::Refresh));
+        let body = url::form_urlencoded::serialize_owned(&vec![Param::Ttl(ttl).into(), Param::Refresh(true).into()]);
+        let response = try!(self.send(Method::Put, EtcdObject::Keys, key, &vec![AtomicOp::PrevExist(true).into()], Some(&body)));
+        let result = try!(EtcdClient::to_etcd_result(response));
+
+        return Ok(result.node);
+    }
+
+    /// set the value of a key only if all of the conditions hold
+    ///  returns CompareResult::Failed with the current etcd index if they did not.
+    ///
+    /// A swap conditioned on PrevIndex is retried after a transient failure, see RetryPolicy. If the first attempt was
+    ///  applied but its response was lost, the retry returns Failed although the value was set; read the key to tell
+    ///  whether it holds the value at an index after the PrevIndex.
+    pub fn compare_and_swap(&self, key: &str, value: &str, conditions: &[AtomicOp]) -> Result<CompareResult, EtcdError> {
+        let params: Vec<(String,String)> = conditions.iter().map(|op| (*op).into()).collect();
+        let body = url::form_urlencoded::serialize_owned(&vec![Param::Value(value).into()]);
+
+        return EtcdClient::to_compare_result(self.send(Method::Put, EtcdObject::Keys, key, &params, Some(&body)));
+    }
+
+    /// remove a key only if all of the conditions hold, PrevExist is not supported by etcd for deletes
+    ///  returns CompareResult::Failed with the current etcd index if they did not.
+    ///
+    /// Like compare_and_swap(), a delete conditioned on PrevIndex is retried, and fails with KeyNotFound if the first
+    ///  attempt removed the key but its response was lost.
+    pub fn compare_and_delete(&self, key: &str, conditions: &[AtomicOp]) -> Result<CompareResult, EtcdError> {
+        let params: Vec<(String,String)> = conditions.iter().map(|op| (*op).into()).collect();
+
+        return EtcdClient::to_compare_result(self.send(Method::Delete, EtcdObject::Keys, key, &params, None));
+    }
+
+    /// reset the ttl of an existing key only if all of the conditions hold, watchers are not notified of the refresh
+    ///  returns CompareResult::Failed with the current etcd index if they did not.
+    pub fn compare_and_refresh(&self, key: &str, ttl: u64, conditions: &[AtomicOp]) -> Result<CompareResult, EtcdError> {
+        try!(self.require(Feature::Refresh));
+        let params: Vec<(String,String)> = conditions.iter().map(|op| (*op).into()).collect();
+        let body = url::form_urlencoded::serialize_owned(&vec![Param::Ttl(ttl).into(), Param::Refresh(true).into()]);
+
+        return EtcdClient::to_compare_result(self.send(Method::Put, EtcdObject::Keys, key, &params, Some(&body)));
+    }
+
+    /// a failed comparison is an expected outcome of the atomic operations, not an error
+    fn to_compare_result(response: Result<TransportResponse, EtcdError>) -> Result<CompareResult, EtcdError> {
+        return match response.and_then(EtcdClient::to_etcd_result) {
+            Ok(result) => Ok(CompareResult::Success(result)),
+            Err(EtcdError::Api{ code, cause, index, .. }) if code == EtcdErrorCode::TestFailed || code == EtcdErrorCode::NodeExist => {
+                Ok(CompareResult::Failed{ code: code, cause: cause, index: index })
+            },
+            Err(e) => Err(e),
+        };
+    }
 
-    //// update an existing key with a given value
-	//fn update(key: &str, value: &str) {}
+    /// create a new key, fails with NodeExist if the key already exists
+    ///  the key is removed after ttl seconds, if specified.
+    ///  returns the new node.
+    pub fn create(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<Option<EtcdNode>, EtcdError> {
+        let body = url::form_urlencoded::serialize_owned(&EtcdClient::with_ttl(vec![Param::Value(value).into()], ttl));
+        let response = try!(self.send(Method::Put, EtcdObject::Keys, key, &vec![AtomicOp::PrevExist(false).into()], Some(&body)));
+        let result = try!(EtcdClient::to_etcd_result(response));
 
-    //// update an existing directory
-	//fn update_dir(key: &str, value: &Vec<String>) {}
+        return Ok(result.node);
+    }
+
+    /// update the value of an existing key, fails with KeyNotFound if the key does not exist
+    ///  the key is removed after ttl seconds, if specified, otherwise any existing ttl is removed.
+    ///  returns the previous node.
+    pub fn update(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<Option<EtcdNode>, EtcdError> {
+        let body = url::form_urlencoded::serialize_owned(&EtcdClient::with_ttl(vec![Param::Value(value).into()], ttl));
+        let response = try!(self.send(Method::Put, EtcdObject::Keys, key, &vec![AtomicOp::PrevExist(true).into()], Some(&body)));
+        let result = try!(EtcdClient::to_etcd_result(response));
+
+        return Ok(result.previous_node);
+    }
+
+    /// create a new directory, fails with NodeExist if the key already exists
+    ///  the directory and everything in it is removed after ttl seconds, if specified.
+    ///  returns the new node.
+    pub fn create_dir(&self, dir: &str, ttl: Option<u64>) -> Result<Option<EtcdNode>, EtcdError> {
+        let body = url::form_urlencoded::serialize_owned(&EtcdClient::with_ttl(vec![Param::Dir(true).into()], ttl));
+        let response = try!(self.send(Method::Put, EtcdObject::Keys, dir, &vec![AtomicOp::PrevExist(false).into()], Some(&body)));
+        let result = try!(EtcdClient::to_etcd_result(response));
+
+        return Ok(result.node);
+    }
+
+    /// change the ttl of an existing directory, fails with KeyNotFound if the directory does not exist
+    ///  the directory is removed after ttl seconds, if None the existing ttl is removed.
+    ///  returns the updated node.
+    pub fn update_dir(&self, dir: &str, ttl: Option<u64>) -> Result<Option<EtcdNode>, EtcdError> {
+        // an empty ttl clears the ttl
+        let ttl_param: (String, String) = match ttl {
+            Some(ttl) => Param::Ttl(ttl).into(),
+            None => ("ttl".to_string(), String::new()),
+        };
+
+        let body = url::form_urlencoded::serialize_owned(&vec![Param::Dir(true).into(), ttl_param]);
+        let response = try!(self.send(Method::Put, EtcdObject::Keys, dir, &vec![AtomicOp::PrevExist(true).into()], Some(&body)));
+        let result = try!(EtcdClient::to_etcd_result(response));
+
+        return Ok(result.node);
+    }
 
     /// watch a key for changes
     ///  blocks until the key changes
-    /// TODO need to accept the wait index option to guarantee no races.
-	fn watch<'a>(&self, key: &'a str) -> Result<EtcdResult, etcd_error::EtcdError> {
-        let url = self.build_url(EtcdObject::Keys, key, &vec![Param::Wait(true).into()]);
+    ///
+    /// wait_index: return the first change at or after this index, rather than waiting for the next change. To watch
+    ///  without missing any events, pass the modified_index of the last event processed plus one.
+    /// recursive: watch for changes to any key in the directory
+    pub fn watch<'a>(&self, key: &'a str, wait_index: Option<u64>, recursive: bool) -> Result<EtcdResult, EtcdError> {
+        // this will block until the server returns, see watch_async() for a watch which doesn't
+        return self.watch_until(key, wait_index, recursive, None);
+    }
+
+    /// watch(), ending with an error once the cancel token is cancelled
+    fn watch_until(&self, key: &str, wait_index: Option<u64>, recursive: bool, cancel: Option<&CancelToken>) -> Result<EtcdResult, EtcdError> {
+        let mut params = vec![Param::Wait(true).into()];
+        if let Some(index) = wait_index {
+            params.push(Param::WaitIndex(index).into());
+        }
 
-        let response: hyper::client::response::Response = try!(Client::new()
-                                                                .get(url)
-                                                                .header(EtcdClient::accept_json_header())
-                                                                .send());
+        if recursive {
+            params.push(Param::Recursive(true).into());
+        }
+
+        let response = try!(self.send_watch(key, &params, cancel));
+
+        // etcd answers a watch without an event with an empty body once its own timeout passes
+        if response.status.is_success() && response.body.trim().is_empty() {
+            return Err(EtcdError::WatchTimeout{ index: response.headers.get::<XEtcdIndex>().map(|h| **h) });
+        }
 
-        // this will block until the server returns, TODO we should really return a future
         return EtcdClient::to_etcd_result(response);
     }
 
 
-    //// watch a key for changes and exec an executable
-	//fn exec_watch(key: String) {}
+    /// continuously watch a key for changes, see Watcher
+    ///  wait_index and recursive are the same as for watch()
+    pub fn watcher<'a>(&'a self, key: &str, wait_index: Option<u64>, recursive: bool) -> Watcher<'a> {
+        return Watcher::new(self, key, wait_index, recursive);
+    }
+
+    /// continuously watch a key for changes without blocking, the events of a Watcher are delivered by the WatchHandle
+    ///  until it's cancelled. wait_index and recursive are the same as for watch()
+    pub fn watch_async(&self, key: &str, wait_index: Option<u64>, recursive: bool) -> WatchHandle {
+        return WatchHandle::new(self.clone(), key, wait_index, recursive);
+    }
+
+    /// watch a key for changes and run the command on each, blocks until the command can't be run, see Watcher::exec
+    ///  command is the program followed by its arguments, the event is passed in the environment variables
+    ///  ETCD_WATCH_ACTION, ETCD_WATCH_KEY, ETCD_WATCH_VALUE and ETCD_WATCH_MODIFIED_INDEX.
+    ///  recursive is the same as for watch()
+    pub fn exec_watch(&self, key: &str, recursive: bool, command: &[&str]) -> Result<(), EtcdError> {
+        return self.watcher(key, None, recursive).exec(command);
+    }
 
-    // member		member add, remove and list subcommands
     // upgrade		upgrade an old version etcd cluster to a new version
 }
@@ -0,0 +1,68 @@
+use rand;
+use std::cmp;
+use std::time::Duration;
+
+/// RetryPolicy decides how many times, and how long after a transient failure, a request is sent again.
+///
+/// Only idempotent requests are retried: reads, watches, and writes conditioned on a prevIndex. Other writes,
+///  i.e. the POSTs of create_in_order, may have been applied before the failure and are never retried.
+///
+/// A failure is transient if no member could be reached, the member timed out, or the member answered with a
+///  server error such as a leader election in progress.
+///
+/// The backoff is exponential with full jitter, the delay before each retry is a random duration of up to
+///  initial_backoff * 2^retry, capped at max_backoff.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// the number of times a request is retried, 0 disables retries
+    pub max_retries: u32,
+    /// the longest delay before the first retry
+    pub initial_backoff: Duration,
+    /// the longest delay before any retry
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// never retry, the default
+    pub fn none() -> RetryPolicy {
+        return RetryPolicy::new(0);
+    }
+
+    /// retry up to max_retries times, with a backoff starting at 50ms and capped at 2s
+    pub fn new(max_retries: u32) -> RetryPolicy {
+        return RetryPolicy{ max_retries: max_retries,
+                            initial_backoff: Duration::from_millis(50),
+                            max_backoff: Duration::from_millis(2000) };
+    }
+
+    /// the delay before the retry, retry is 0 for the first retry
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let initial_ms = to_millis(self.initial_backoff);
+        let max_ms = to_millis(self.max_backoff);
+        let ceiling_ms = if retry < 32 { cmp::min(max_ms, initial_ms.saturating_mul(1 << retry)) } else { max_ms };
+
+        return Duration::from_millis((rand::random::<f64>() * ceiling_ms as f64) as u64);
+    }
+}
+
+fn to_millis(duration: Duration) -> u64 {
+    return duration.as_secs() * 1000 + (duration.subsec_nanos() / 1000000) as u64;
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+  use super::RetryPolicy;
+
+  #[test]
+  fn backoff_test() {
+    let policy = RetryPolicy{ max_retries: 10, initial_backoff: Duration::from_millis(100), max_backoff: Duration::from_millis(1000) };
+
+    for _ in 0..100 {
+      assert!(policy.backoff(0) <= Duration::from_millis(100));
+      assert!(policy.backoff(2) <= Duration::from_millis(400));
+      assert!(policy.backoff(9) <= Duration::from_millis(1000));
+      assert!(policy.backoff(64) <= Duration::from_millis(1000));
+    }
+  }
+}
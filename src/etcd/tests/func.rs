@@ -1,19 +1,32 @@
 /// These functional tests are not designed to test the functionality of etcd, but instead that the interaction with
-///  it is correct, i.e. that each codepath works. Most likely it would be better to mock most of these tests
+///  it is correct, i.e. that each codepath works. They run against the MockEtcd, or against the etcd at ETCD_ENDPOINT
+///  if it's set, i.e. ETCD_ENDPOINT=http://localhost:4001 cargo test
 
 
-use etcd::EtcdClient;
+use etcd::{AtomicOp, EtcdClient, EtcdClientBuilder};
+use etcd::backup::{ConflictPolicy, RestorePolicy};
+use etcd::etcd_error::EtcdErrorCode;
 use etcd::etcd_node::EtcdNode;
 use etcd::etcd_error::EtcdError;
-use etcd::etcd_result::EtcdResult;
+use etcd::etcd_result::CompareResult;
+use etcd::etcd_version::Feature;
+use etcd::etcd_watcher::RESYNC_ACTION;
 
+use std::env;
 use std::thread;
+use std::time::Duration;
+use testing::MockEtcd;
 
-static TEST_HOST: &'static str = "localhost";
-static TEST_PORT: u16 = 4001;
+/// nothing should be listening here, used to test failover
+static DEAD_ENDPOINT: &'static str = "http://localhost:1";
 
 static TEST_DIR: &'static str = "rs_test_dir";
 static TEST_KEY: &'static str = "rs_test_dir/rs_test_key";
+static TEST_CAS_KEY: &'static str = "rs_test_dir/rs_test_cas_key";
+static TEST_TTL_KEY: &'static str = "rs_test_dir/rs_test_ttl_key";
+static TEST_CREATE_KEY: &'static str = "rs_test_dir/rs_test_create_key";
+static TEST_CREATE_DIR: &'static str = "rs_test_dir/rs_test_create_dir";
+static TEST_RESTORE_DIR: &'static str = "rs_test_restore_dir";
 
 /// in order to run the tests in order, but also have an indication of which test we were in when it ran.
 macro_rules! run {
@@ -26,27 +39,66 @@ macro_rules! run {
   )
 }
 
-fn client() -> EtcdClient {
-    return EtcdClient{etcd_host: TEST_HOST.to_string(), etcd_port: TEST_PORT};
+fn client(endpoint: &str) -> EtcdClient {
+    return EtcdClientBuilder::new().endpoint(endpoint).build().unwrap();
 }
 
 /// these are functional tests that need to be executed in order...
 #[test]
 fn ordered_tests() {
-    run!(test_remove_dir(false));
-    run!(test_make_dir());
-	run!(test_set());
-    run!(test_get());
-    run!(test_list());
-    run!(test_watch());
-	run!(test_remove());
-    run!(test_index_append());
+    let mock = MockEtcd::new();
+    let endpoint = env::var("ETCD_ENDPOINT").unwrap_or(mock.endpoint());
+
+    run!(test_remove_dir(&endpoint, false));
+    run!(test_make_dir(&endpoint));
+	run!(test_set(&endpoint));
+    run!(test_get(&endpoint));
+    run!(test_failover(&endpoint));
+    run!(test_cluster_health(&endpoint));
+    run!(test_members_list(&endpoint));
+    run!(test_stats(&endpoint));
+    run!(test_version(&endpoint));
+    run!(test_compare_and_swap(&endpoint));
+    run!(test_compare_and_delete(&endpoint));
+    run!(test_ttl(&endpoint));
+    run!(test_create_update(&endpoint));
+    run!(test_create_update_dir(&endpoint));
+    run!(test_list(&endpoint));
+    run!(test_backup_restore(&endpoint));
+    run!(test_watch(&endpoint));
+    run!(test_watch_cancel(&endpoint));
+    run!(test_watch_index(&endpoint));
+    run!(test_watcher(&endpoint));
+	run!(test_remove(&endpoint));
+    run!(test_index_append(&endpoint));
+
+    run!(test_remove_dir(&endpoint, true));
+}
+
+/// the MockEtcd only keeps two events, so the watcher has to resync from the directory
+#[test]
+fn watcher_resync_test() {
+    let mock = MockEtcd::with_history(2);
+    let client = mock.client();
+
+    let index = client.create(TEST_KEY, "resync1", None).unwrap().unwrap().modified_index as u64;
+    for value in &["resync2", "resync3", "resync4"] {
+        assert!(client.set(TEST_KEY, value, None).is_ok());
+    }
+
+    let mut watcher = client.watcher(TEST_DIR, Some(index), true);
+    let event = watcher.next().unwrap().unwrap();
 
-    run!(test_remove_dir(true));
+    assert_eq!(event.action, RESYNC_ACTION);
+    assert_eq!(watcher.wait_index(), Some(event.x_etcd_index + 1));
+
+    let dir = event.node.unwrap();
+    assert!(dir.dir);
+    assert_eq!(dir.nodes.unwrap()[0].value, Some("resync4".to_string()));
 }
 
-fn test_remove_dir(assert_success: bool) {
-    let client = client();
+fn test_remove_dir(endpoint: &str, assert_success: bool) {
+    let client = client(endpoint);
     let result = client.remove_dir(TEST_DIR, true);
 
     if !assert_success {
@@ -65,9 +117,9 @@ fn test_remove_dir(assert_success: bool) {
     assert!(old_dir.unwrap().dir);
 }
 
-fn test_make_dir() {
-	let client = client();
-	let result = client.make_dir(TEST_DIR); // now set it
+fn test_make_dir(endpoint: &str) {
+	let client = client(endpoint);
+	let result = client.make_dir(TEST_DIR, None); // now set it
 
 	if let Err(e) = result {
 	    panic!("error: {:?}", e);
@@ -77,10 +129,10 @@ fn test_make_dir() {
     assert!(result.unwrap().unwrap().dir)
 }
 
-fn test_set() {
-	let client = client();
-	let result = client.set(TEST_KEY, ""); // null it...
-	let result = client.set(TEST_KEY, "testvalue"); // now set it
+fn test_set(endpoint: &str) {
+	let client = client(endpoint);
+	let result = client.set(TEST_KEY, "", None); // null it...
+	let result = client.set(TEST_KEY, "testvalue", None); // now set it
 
 	if let Err(e) = result {
 	  panic!("error: {:?}", e);
@@ -91,8 +143,8 @@ fn test_set() {
     assert_eq!(result.unwrap().unwrap().value.unwrap(), ""); // should have been null before because of the first clear...
 }
 
-fn test_get() {
-	let client = client();
+fn test_get(endpoint: &str) {
+	let client = client(endpoint);
 
 	let result = client.get(TEST_KEY);
 
@@ -105,8 +157,168 @@ fn test_get() {
 	assert_eq!(object.unwrap().value.unwrap(), "testvalue");
 }
 
-fn test_list() {
-    let client = client();
+fn test_failover(endpoint: &str) {
+    let client = EtcdClientBuilder::new().endpoint(DEAD_ENDPOINT).endpoint(endpoint).build().unwrap();
+
+    let result = client.get(TEST_KEY);
+
+    if let Err(e) = result {
+      panic!("error: {:?}", e);
+    }
+
+    assert_eq!(result.unwrap().unwrap().value.unwrap(), "testvalue");
+}
+
+fn test_cluster_health(endpoint: &str) {
+    let health = client(endpoint).cluster_health().unwrap();
+
+    assert!(health.leader().is_some(), "no leader: {:?}", health);
+    assert!(health.members.iter().any(|m| m.reachable && m.raft_term.is_some()));
+}
+
+fn test_version(endpoint: &str) {
+    let client = EtcdClientBuilder::new().endpoint(endpoint).negotiate(true).build().unwrap();
+    let capabilities = client.capabilities().unwrap();
+
+    // the tests refresh keys
+    assert!(capabilities.supports(Feature::Refresh), "{:?}", capabilities);
+    assert_eq!(client.version().unwrap().server, capabilities.version.server);
+}
+
+fn test_stats(endpoint: &str) {
+    let client = client(endpoint);
+
+    let self_stats = client.self_stats().unwrap();
+    assert!(!self_stats.id.is_empty());
+
+    if self_stats.is_leader() {
+        assert_eq!(client.leader_stats().unwrap().leader, self_stats.id);
+    }
+
+    // the test key was set and read by the tests before
+    let store_stats = client.store_stats().unwrap();
+    assert!(store_stats.sets_success > 0 && store_stats.gets_success > 0, "{:?}", store_stats);
+}
+
+fn test_members_list(endpoint: &str) {
+    let members = client(endpoint).members_list().unwrap();
+
+    assert!(!members.is_empty());
+    assert!(members.iter().all(|m| !m.id.is_empty() && !m.peer_urls.is_empty()), "{:?}", members);
+}
+
+fn test_compare_and_swap(endpoint: &str) {
+    let client = client(endpoint);
+
+    // the key doesn't exist yet
+    let result = client.compare_and_swap(TEST_CAS_KEY, "cas1", &[AtomicOp::PrevExist(false)]).unwrap();
+    let created_index = match result {
+        CompareResult::Success(r) => r.node.unwrap().modified_index as u64,
+        f => panic!("expected success: {:?}", f),
+    };
+
+    // now it does
+    match client.compare_and_swap(TEST_CAS_KEY, "cas2", &[AtomicOp::PrevExist(false)]).unwrap() {
+        CompareResult::Failed{ code, index, .. } => {
+            assert_eq!(code, EtcdErrorCode::NodeExist);
+            assert!(index >= created_index);
+        },
+        s => panic!("expected failure: {:?}", s),
+    }
+
+    match client.compare_and_swap(TEST_CAS_KEY, "cas2", &[AtomicOp::PrevValue("wrong")]).unwrap() {
+        CompareResult::Failed{ code, .. } => assert_eq!(code, EtcdErrorCode::TestFailed),
+        s => panic!("expected failure: {:?}", s),
+    }
+
+    match client.compare_and_swap(TEST_CAS_KEY, "cas2", &[AtomicOp::PrevValue("cas1"), AtomicOp::PrevIndex(created_index)]).unwrap() {
+        CompareResult::Success(r) => {
+            assert_eq!(r.action, "compareAndSwap");
+            assert_eq!(r.node.unwrap().value.unwrap(), "cas2");
+            assert_eq!(r.previous_node.unwrap().value.unwrap(), "cas1");
+        },
+        f => panic!("expected success: {:?}", f),
+    }
+}
+
+fn test_compare_and_delete(endpoint: &str) {
+    let client = client(endpoint);
+
+    let result = client.compare_and_delete(TEST_CAS_KEY, &[AtomicOp::PrevValue("wrong")]).unwrap();
+    assert!(!result.is_success());
+
+    match client.compare_and_delete(TEST_CAS_KEY, &[AtomicOp::PrevValue("cas2")]).unwrap() {
+        CompareResult::Success(r) => {
+            assert_eq!(r.action, "compareAndDelete");
+            assert_eq!(r.previous_node.unwrap().value.unwrap(), "cas2");
+        },
+        f => panic!("expected success: {:?}", f),
+    }
+}
+
+fn test_ttl(endpoint: &str) {
+    let client = client(endpoint);
+
+    assert!(client.set(TEST_TTL_KEY, "ttl", Some(60)).is_ok());
+
+    let node = client.get(TEST_TTL_KEY).unwrap().unwrap();
+    assert!(node.ttl.unwrap() <= 60);
+    assert!(node.expiration.is_some());
+
+    let node = client.refresh(TEST_TTL_KEY, 120).unwrap().unwrap();
+    assert!(node.ttl.unwrap() > 60);
+    assert_eq!(node.value.unwrap(), "ttl");
+
+    assert!(client.remove(TEST_TTL_KEY).is_ok());
+}
+
+fn test_create_update(endpoint: &str) {
+    let client = client(endpoint);
+
+    match client.update(TEST_CREATE_KEY, "update", None) {
+        Err(e) => assert_eq!(e.error_code(), Some(EtcdErrorCode::KeyNotFound)),
+        r => panic!("expected KeyNotFound: {:?}", r),
+    }
+
+    let node = client.create(TEST_CREATE_KEY, "create", None).unwrap().unwrap();
+    assert_eq!(node.value.unwrap(), "create");
+
+    match client.create(TEST_CREATE_KEY, "create", None) {
+        Err(e) => assert_eq!(e.error_code(), Some(EtcdErrorCode::NodeExist)),
+        r => panic!("expected NodeExist: {:?}", r),
+    }
+
+    let prev = client.update(TEST_CREATE_KEY, "update", Some(60)).unwrap().unwrap();
+    assert_eq!(prev.value.unwrap(), "create");
+    assert!(client.get(TEST_CREATE_KEY).unwrap().unwrap().ttl.is_some());
+
+    assert!(client.remove(TEST_CREATE_KEY).is_ok());
+}
+
+fn test_create_update_dir(endpoint: &str) {
+    let client = client(endpoint);
+
+    let node = client.create_dir(TEST_CREATE_DIR, Some(60)).unwrap().unwrap();
+    assert!(node.dir);
+    assert!(node.ttl.is_some());
+
+    match client.create_dir(TEST_CREATE_DIR, None) {
+        Err(e) => assert_eq!(e.error_code(), Some(EtcdErrorCode::NodeExist)),
+        r => panic!("expected NodeExist: {:?}", r),
+    }
+
+    let node = client.update_dir(TEST_CREATE_DIR, None).unwrap().unwrap();
+    assert!(node.ttl.is_none());
+
+    let node = client.create_in_order(TEST_CREATE_DIR, "in_order", Some(60)).unwrap().unwrap();
+    assert!(node.key.starts_with(&format!("/{}/", TEST_CREATE_DIR)));
+    assert_eq!(node.value.unwrap(), "in_order");
+
+    assert!(client.remove_dir(TEST_CREATE_DIR, true).is_ok());
+}
+
+fn test_list(endpoint: &str) {
+    let client = client(endpoint);
     let result = client.get(TEST_DIR);
 
     if let Err(e) = result {
@@ -129,39 +341,105 @@ fn test_list() {
 
     assert!(zero.is_some());
     assert_eq!(zero, &Some("testvalue".to_string()));
+
+    // etcd returns the root without a key or indexes
+    let root = client.get("/").unwrap().unwrap();
+    assert_eq!(&root.key as &str, "/");
+    assert!(root.nodes.unwrap().iter().any(|n| n.key == format!("/{}", TEST_DIR)));
 }
 
-fn test_watch() {
-    let test_thread = thread::current();
+fn test_backup_restore(endpoint: &str) {
+    let client = client(endpoint);
 
-    let watch_join: thread::JoinHandle<Result<EtcdResult, EtcdError>> = thread::spawn(move || {
-        let client = client();
-        test_thread.unpark();
+    // left behind by a run which failed
+    let _ = client.remove_dir(TEST_RESTORE_DIR, true);
 
-        // there is a potential race here... i.e. we unparked the test thread before we watched...
-        //  this blocks, so we had to do it above, TODO non-blocking future should be returned.
-        client.watch(TEST_KEY) // this should block until we change the value...
-    });
+    let mut file: Vec<u8> = vec![];
+    let backup = client.backup(TEST_DIR, &mut file).unwrap();
+    assert!(backup.keys > 0);
 
-    // make sure this primary thread pauses for the child to complete the watch...
-    thread::park();
+    let restored = client.restore(&mut &file[..], TEST_RESTORE_DIR, &RestorePolicy::new(ConflictPolicy::Fail)).unwrap();
+    assert_eq!(restored.created, backup.keys + backup.dirs);
 
-    // now change the value...
-    let client = client();
-    assert!(client.set(TEST_KEY, "testwatch").is_ok());
+    let key = format!("{}/{}", TEST_RESTORE_DIR, &TEST_KEY[TEST_DIR.len() + 1..]);
+    assert_eq!(client.get(&key).unwrap().unwrap().value, client.get(TEST_KEY).unwrap().unwrap().value);
+
+    client.remove_dir(TEST_RESTORE_DIR, true).unwrap();
+}
 
-    let etcd_result = watch_join.join().unwrap().unwrap();
+fn test_watch(endpoint: &str) {
+    let client = client(endpoint);
+    let index = client.get(TEST_KEY).unwrap().unwrap().modified_index as u64;
+
+    // the watch may not have reached etcd before the change, the wait index guarantees it's seen either way
+    let watch = client.watch_async(TEST_KEY, Some(index + 1), false);
+    assert!(client.set(TEST_KEY, "testwatch", None).is_ok());
+
+    let etcd_result = watch.recv().unwrap().unwrap();
     assert_eq!(etcd_result.action, "set");
+    assert!(etcd_result.x_etcd_index > 0);
+    assert!(etcd_result.x_raft_term > 0);
     assert_eq!(etcd_result.node.unwrap().value.unwrap(), "testwatch");
     assert_eq!(etcd_result.previous_node.unwrap().value.unwrap(), "testvalue");
 
+    watch.cancel();
+
+    // reset the value
+    assert!(client.set(TEST_KEY, "testvalue", None).is_ok()); // now set it
+}
+
+fn test_watch_cancel(endpoint: &str) {
+    let client = client(endpoint);
+
+    // nothing changes the key, so the watch is blocked in the long poll when it's cancelled
+    let watch = client.watch_async(TEST_KEY, None, false);
+    thread::sleep(Duration::from_millis(100));
+    assert!(watch.try_recv().is_err());
+
+    watch.cancel();
+}
+
+fn test_watch_index(endpoint: &str) {
+    let client = client(endpoint);
+    let index = client.get(TEST_KEY).unwrap().unwrap().modified_index as u64;
+
+    // the change happens before the watch, the wait index guarantees it is still seen
+    assert!(client.set(TEST_KEY, "testwatchindex", None).is_ok());
+
+    let etcd_result = client.watch(TEST_DIR, Some(index + 1), true).unwrap();
+    assert_eq!(etcd_result.action, "set");
+    assert!(etcd_result.modified_index().unwrap() > index);
+
+    let node = etcd_result.node.unwrap();
+    assert!(node.key.ends_with(TEST_KEY));
+    assert_eq!(node.value.unwrap(), "testwatchindex");
+
     // reset the value
-    assert!(client.set(TEST_KEY, "testvalue").is_ok()); // now set it
+    assert!(client.set(TEST_KEY, "testvalue", None).is_ok());
+}
+
+fn test_watcher(endpoint: &str) {
+    let client = client(endpoint);
+    let index = client.get(TEST_KEY).unwrap().unwrap().modified_index as u64;
 
+    assert!(client.set(TEST_KEY, "testwatcher1", None).is_ok());
+    assert!(client.set(TEST_KEY, "testwatcher2", None).is_ok());
+
+    let mut watcher = client.watcher(TEST_KEY, Some(index + 1), false);
+
+    let first = watcher.next().unwrap().unwrap();
+    assert_eq!(first.node.unwrap().value.unwrap(), "testwatcher1");
+
+    let second = watcher.next().unwrap().unwrap();
+    assert_eq!(watcher.wait_index(), Some(second.modified_index().unwrap() + 1));
+    assert_eq!(second.node.unwrap().value.unwrap(), "testwatcher2");
+
+    // reset the value
+    assert!(client.set(TEST_KEY, "testvalue", None).is_ok());
 }
 
-fn test_remove() {
-	let client = client();
+fn test_remove(endpoint: &str) {
+	let client = client(endpoint);
 	let result = client.remove(TEST_KEY); // now set it
 
 	if let Err(e) = result {
@@ -175,9 +453,9 @@ fn test_remove() {
 
 /// this test focuses on creating ordered indexes, it combines both the creation and listing for the
 ///  tests, which is generally inapropriate.
-fn test_index_append() {
+fn test_index_append(endpoint: &str) {
     let index: &str = &format!("{}/{}", TEST_DIR, "test_index");
-    let client = client();
+    let client = client(endpoint);
 
     let result = client.index_append(index, "test_value1");
 
@@ -215,8 +493,8 @@ fn test_index_append() {
     assert_eq!(one, &Some("test_value2".to_string()));
 }
 
-fn test_rm_dir() {
-    let client = EtcdClient{etcd_host: TEST_HOST.to_string(), etcd_port: TEST_PORT};
+fn test_rm_dir(endpoint: &str) {
+    let client = client(endpoint);
     let result = client.remove_dir(TEST_DIR, false);
 
     if let Err(e) = result {
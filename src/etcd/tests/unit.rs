@@ -0,0 +1,463 @@
+use hyper::error::HttpError;
+use hyper::header::Headers;
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use hyper::Url;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use etcd::{AtomicOp, EtcdClient, EtcdClientBuilder};
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_health::{ClusterStatus, MemberCheck};
+use etcd::etcd_version::{Feature, Version};
+use etcd::retry::RetryPolicy;
+use etcd::transport::{self, RequestBody, Transport, TransportRequest, TransportResponse};
+use testing::MockTransport;
+
+#[test]
+fn atomic_op_params_test() {
+    assert_eq!(AtomicOp::PrevValue("value").into(), ("prevValue".to_string(), "value".to_string()));
+    assert_eq!(AtomicOp::PrevIndex(12).into(), ("prevIndex".to_string(), "12".to_string()));
+    assert_eq!(AtomicOp::PrevExist(false).into(), ("prevExist".to_string(), "false".to_string()));
+}
+
+#[test]
+fn mock_transport_test() {
+    let client = MockTransport::new().client();
+
+    assert!(client.set("/unit/key", "one", None).unwrap().is_none());
+    assert_eq!(client.get("/unit/key").unwrap().unwrap().value.unwrap(), "one");
+
+    match client.get("/unit/missing") {
+        Err(e) => assert_eq!(e.error_code(), Some(EtcdErrorCode::KeyNotFound)),
+        r => panic!("expected KeyNotFound: {:?}", r),
+    }
+}
+
+/// fails to connect to the first member with the error, the rest are answered by the mock
+struct UnreachableTransport {
+    mock: MockTransport,
+    unreachable: String,
+    error: io::ErrorKind,
+}
+
+impl Transport for UnreachableTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        if request.url.serialize().starts_with(&self.unreachable) {
+            let error = transport::connect_error(io::Error::new(self.error, "unreachable"));
+            return Err(EtcdError::from(HttpError::HttpIoError(error)));
+        }
+
+        return self.mock.send(request);
+    }
+}
+
+fn unreachable_client(error: io::ErrorKind) -> EtcdClient {
+    let transport = UnreachableTransport{ mock: MockTransport::new(), unreachable: "http://10.0.0.1:4001".to_string(), error: error };
+
+    return EtcdClientBuilder::new().endpoints(&["http://10.0.0.1:4001", "http://10.0.0.2:4001"])
+                                   .transport(Box::new(transport))
+                                   .negotiate(false)
+                                   .build()
+                                   .unwrap();
+}
+
+#[test]
+fn transport_failover_test() {
+    let client = unreachable_client(io::ErrorKind::ConnectionRefused);
+
+    client.set("/unit/failover", "two", None).unwrap();
+    assert_eq!(client.get("/unit/failover").unwrap().unwrap().value.unwrap(), "two");
+}
+
+#[test]
+fn connect_timeout_failover_test() {
+    // nothing was sent to the member which timed out, so even a request which isn't idempotent fails over
+    let client = unreachable_client(io::ErrorKind::TimedOut);
+
+    let node = client.create_in_order("/unit/failover_queue", "job", None).unwrap().unwrap();
+    assert_eq!(node.value.unwrap(), "job");
+}
+
+/// resets the connection of the first failures requests, the rest are answered by the mock
+struct FlakyTransport {
+    mock: MockTransport,
+    failures: usize,
+    sent: Arc<AtomicUsize>,
+}
+
+impl Transport for FlakyTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        if self.sent.fetch_add(1, Ordering::SeqCst) < self.failures {
+            return Err(EtcdError::from(HttpError::HttpIoError(io::Error::new(io::ErrorKind::ConnectionReset, "reset"))));
+        }
+
+        return self.mock.send(request);
+    }
+}
+
+fn flaky_client(failures: usize, sent: Arc<AtomicUsize>) -> EtcdClient {
+    let retry = RetryPolicy{ max_retries: 2, initial_backoff: Duration::from_millis(1), max_backoff: Duration::from_millis(1) };
+    let transport = FlakyTransport{ mock: MockTransport::new(), failures: failures, sent: sent };
+
+    return EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001")
+                                   .transport(Box::new(transport))
+                                   .retry_policy(retry)
+                                   .negotiate(false)
+                                   .build()
+                                   .unwrap();
+}
+
+#[test]
+fn retry_idempotent_test() {
+    let sent = Arc::new(AtomicUsize::new(0));
+    let client = flaky_client(2, sent.clone());
+
+    match client.get("/unit/retry") {
+        Err(e) => assert_eq!(e.error_code(), Some(EtcdErrorCode::KeyNotFound)),
+        r => panic!("expected KeyNotFound: {:?}", r),
+    }
+
+    assert_eq!(sent.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn retry_exhausted_test() {
+    let sent = Arc::new(AtomicUsize::new(0));
+    let client = flaky_client(3, sent.clone());
+
+    match client.get("/unit/retry") {
+        Err(EtcdError::HttpError(HttpError::HttpIoError(_))) => (),
+        r => panic!("expected HttpIoError: {:?}", r),
+    }
+
+    assert_eq!(sent.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn no_retry_post_test() {
+    let sent = Arc::new(AtomicUsize::new(0));
+    let client = flaky_client(1, sent.clone());
+
+    match client.create_in_order("/unit/queue", "job", None) {
+        Err(EtcdError::HttpError(HttpError::HttpIoError(_))) => (),
+        r => panic!("expected HttpIoError: {:?}", r),
+    }
+
+    assert_eq!(sent.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn watch_async_test() {
+    let client = MockTransport::new().client();
+    let index = client.create("/unit/watched", "zero", None).unwrap().unwrap().modified_index as u64;
+    let watch = client.watch_async("/unit/watched", Some(index + 1), false);
+
+    client.set("/unit/watched", "one", None).unwrap();
+    client.set("/unit/watched", "two", None).unwrap();
+
+    assert_eq!(watch.recv().unwrap().unwrap().node.unwrap().value.unwrap(), "one");
+    assert_eq!(watch.recv().unwrap().unwrap().node.unwrap().value.unwrap(), "two");
+
+    watch.cancel();
+}
+
+/// answers the first watches with the empty body etcd sends once a watch times out, at the current index, the rest
+///  are answered by the mock
+struct TimeoutWatchTransport {
+    mock: MockTransport,
+    timeouts: usize,
+    watches: AtomicUsize,
+    /// the key set after each timeout, a change between the watch which timed out and the next
+    change: Option<&'static str>,
+}
+
+impl TimeoutWatchTransport {
+    fn mock_request(&self, method: Method, key: &str, body: Option<RequestBody>) -> Result<TransportResponse, EtcdError> {
+        let url = Url::parse(&format!("http://10.0.0.1:4001/v2/keys{}", key)).unwrap();
+        return self.mock.send(&TransportRequest{ method: method, url: &url, body: body, long_poll: false, cancel: None });
+    }
+}
+
+impl Transport for TimeoutWatchTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        if request.long_poll && self.watches.fetch_add(1, Ordering::SeqCst) < self.timeouts {
+            let headers = try!(self.mock_request(Method::Get, "/", None)).headers;
+            if let Some(key) = self.change {
+                try!(self.mock_request(Method::Put, key, Some(RequestBody::Form("value=between"))));
+            }
+
+            return Ok(TransportResponse{ status: StatusCode::Ok, headers: headers, body: String::new() });
+        }
+
+        return self.mock.send(request);
+    }
+}
+
+fn timeout_watch_client(timeouts: usize, change: Option<&'static str>) -> EtcdClient {
+    let transport = TimeoutWatchTransport{ mock: MockTransport::new(), timeouts: timeouts, watches: AtomicUsize::new(0), change: change };
+    return EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001").transport(Box::new(transport)).build().unwrap();
+}
+
+#[test]
+fn watch_timeout_test() {
+    let client = timeout_watch_client(2, None);
+    let index = client.create("/unit/timeout", "zero", None).unwrap().unwrap().modified_index as u64;
+
+    match client.watch("/unit/timeout", Some(index), false) {
+        Err(EtcdError::WatchTimeout{ index: Some(timeout_index) }) => assert_eq!(timeout_index, index),
+        r => panic!("expected WatchTimeout: {:?}", r),
+    }
+
+    // the watcher reissues the watch which timed out
+    let event = client.watcher("/unit/timeout", Some(index), false).next().unwrap().unwrap();
+    assert_eq!(event.node.unwrap().value.unwrap(), "zero");
+}
+
+#[test]
+fn watch_timeout_from_now_test() {
+    let client = timeout_watch_client(1, Some("/unit/between"));
+
+    // the change made after the watch from now timed out is the first event of the next
+    let event = client.watcher("/unit/between", None, false).next().unwrap().unwrap();
+    assert_eq!(event.node.unwrap().value.unwrap(), "between");
+}
+
+/// refuses the connection of the first long polls, the rest are answered by the mock
+struct RefusedWatchTransport {
+    mock: MockTransport,
+    failures: usize,
+    watches: AtomicUsize,
+}
+
+impl Transport for RefusedWatchTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        if request.long_poll && self.watches.fetch_add(1, Ordering::SeqCst) < self.failures {
+            let error = transport::connect_error(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"));
+            return Err(EtcdError::from(HttpError::HttpIoError(error)));
+        }
+
+        return self.mock.send(request);
+    }
+}
+
+#[test]
+fn exec_watch_error_test() {
+    let transport = RefusedWatchTransport{ mock: MockTransport::new(), failures: 2, watches: AtomicUsize::new(0) };
+    let client = EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001").transport(Box::new(transport)).build().unwrap();
+    let index = client.create("/unit/exec", "zero", None).unwrap().unwrap().modified_index as u64;
+
+    // the failed watches are resumed, so the command is run on the event and only failing to start it ends the watch
+    match client.watcher("/unit/exec", Some(index), false).exec(&["/nonexistent/etcd-rs-command"]) {
+        Err(EtcdError::IOError(ref e)) if e.kind() == io::ErrorKind::NotFound => (),
+        r => panic!("expected NotFound: {:?}", r),
+    }
+}
+
+#[test]
+fn cluster_health_test() {
+    let client = MockTransport::new().client();
+    let health = client.cluster_health().unwrap();
+
+    assert_eq!(health.status, ClusterStatus::Healthy);
+    assert_eq!(health.members.len(), 1);
+
+    let member = &health.members[0];
+    assert!(member.reachable && member.healthy && member.leader);
+    assert_eq!(member.raft_term, Some(1));
+    assert!(member.latency.is_some());
+    assert_eq!(&member.member.client_urls[0] as &str, client.endpoints()[0]);
+}
+
+/// answers the stats of the member with an error status, the rest are answered by the mock
+struct StatsErrorTransport {
+    mock: MockTransport,
+}
+
+impl Transport for StatsErrorTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        if request.url.serialize().ends_with("/v2/stats/self") {
+            return Ok(TransportResponse{ status: StatusCode::InternalServerError, headers: Headers::new(), body: "stats failed".to_string() });
+        }
+
+        return self.mock.send(request);
+    }
+}
+
+#[test]
+fn cluster_health_failed_check_test() {
+    let transport = StatsErrorTransport{ mock: MockTransport::new() };
+    let client = EtcdClientBuilder::new().endpoint("http://mock-etcd:4001").transport(Box::new(transport)).build().unwrap();
+    let health = client.cluster_health().unwrap();
+
+    // the member answered /health, but neither its leader nor its term are known
+    let member = &health.members[0];
+    assert!(member.reachable && member.healthy);
+    assert!(!member.leader);
+    assert_eq!(member.raft_term, None);
+    assert_eq!(member.failed_check, Some(MemberCheck::Stats));
+    assert!(member.error.is_some());
+    assert_eq!(health.status, ClusterStatus::Unhealthy);
+}
+
+#[test]
+fn members_test() {
+    let client = MockTransport::new().client();
+    assert_eq!(client.members_list().unwrap().len(), 1);
+
+    let added = client.member_add(&["http://10.0.0.11:2380"]).unwrap();
+    assert_eq!(added.peer_urls, vec!["http://10.0.0.11:2380".to_string()]);
+    assert!(added.client_urls.is_empty());
+
+    match client.member_add(&["http://10.0.0.11:2380"]) {
+        Err(EtcdError::Cluster{ status, .. }) => assert_eq!(status, StatusCode::Conflict),
+        r => panic!("expected a Cluster error: {:?}", r),
+    }
+
+    client.member_update(&added.id, &["http://10.0.0.12:2380"]).unwrap();
+    let members = client.members_list().unwrap();
+    assert_eq!(members.len(), 2);
+    assert_eq!(members.iter().find(|m| m.id == added.id).unwrap().peer_urls, vec!["http://10.0.0.12:2380".to_string()]);
+
+    client.member_remove(&added.id).unwrap();
+    assert_eq!(client.members_list().unwrap().len(), 1);
+
+    match client.member_remove(&added.id) {
+        Err(EtcdError::Cluster{ status, .. }) => assert_eq!(status, StatusCode::NotFound),
+        r => panic!("expected a Cluster error: {:?}", r),
+    }
+}
+
+#[test]
+fn stats_test() {
+    let client = MockTransport::new().client();
+    client.set("/unit/key", "one", None).unwrap();
+    client.get("/unit/key").unwrap();
+
+    let self_stats = client.self_stats().unwrap();
+    assert!(self_stats.is_leader());
+    assert_eq!(self_stats.leader_info.leader, self_stats.id);
+
+    let leader_stats = client.leader_stats().unwrap();
+    assert_eq!(leader_stats.leader, self_stats.id);
+    assert!(leader_stats.followers.is_empty());
+
+    let store_stats = client.store_stats().unwrap();
+    assert_eq!(store_stats.sets_success, 1);
+    assert_eq!(store_stats.gets_success, 1);
+}
+
+/// answers /version as etcd 2.0 did and counts how often it was asked, the rest are answered by the mock
+struct OldVersionTransport {
+    mock: MockTransport,
+    versions: Arc<AtomicUsize>,
+}
+
+impl Transport for OldVersionTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        if request.url.serialize_path() == Some("/version".to_string()) {
+            self.versions.fetch_add(1, Ordering::SeqCst);
+            return Ok(TransportResponse{ status: StatusCode::Ok, headers: Headers::new(), body: "etcd 2.0.13".to_string() });
+        }
+
+        return self.mock.send(request);
+    }
+}
+
+#[test]
+fn negotiate_test() {
+    let client = EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001")
+                                         .transport(Box::new(MockTransport::new()))
+                                         .build()
+                                         .unwrap();
+
+    assert!(client.capabilities().unwrap().supports(Feature::Refresh));
+    client.set("/unit/negotiated", "one", Some(10)).unwrap();
+    client.refresh("/unit/negotiated", 10).unwrap();
+
+    // the version is asked for when the client is built
+    let versions = Arc::new(AtomicUsize::new(0));
+    let old = EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001")
+                                      .transport(Box::new(OldVersionTransport{ mock: MockTransport::new(), versions: versions.clone() }))
+                                      .build()
+                                      .unwrap();
+
+    assert_eq!(versions.load(Ordering::SeqCst), 1);
+    assert_eq!(old.capabilities().unwrap().version.server, Version::new(2, 0, 13));
+    assert!(old.capabilities().unwrap().supports(Feature::Members));
+
+    old.set("/unit/negotiated", "one", Some(10)).unwrap();
+    match old.refresh("/unit/negotiated", 10) {
+        Err(EtcdError::Unsupported{ feature, .. }) => assert_eq!(feature, "refresh"),
+        r => panic!("expected Unsupported: {:?}", r),
+    }
+}
+
+#[test]
+fn lazy_capabilities_test() {
+    let versions = Arc::new(AtomicUsize::new(0));
+    let client = EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001")
+                                         .transport(Box::new(OldVersionTransport{ mock: MockTransport::new(), versions: versions.clone() }))
+                                         .negotiate(false)
+                                         .build()
+                                         .unwrap();
+
+    // nothing is asked until a feature which needs a version is used
+    client.set("/unit/lazy", "one", Some(10)).unwrap();
+    assert_eq!(versions.load(Ordering::SeqCst), 0);
+
+    match client.refresh("/unit/lazy", 10) {
+        Err(EtcdError::Unsupported{ feature, .. }) => assert_eq!(feature, "refresh"),
+        r => panic!("expected Unsupported: {:?}", r),
+    }
+
+    // the clones share what the first call asked
+    assert_eq!(client.clone().members_list().unwrap().len(), 1);
+    assert_eq!(client.capabilities().unwrap().version.server, Version::new(2, 0, 13));
+    assert_eq!(versions.load(Ordering::SeqCst), 1);
+}
+
+/// fails /version as a proxy which doesn't pass it on would, and counts how often it was asked, the rest are answered
+///  by the mock
+struct NoVersionTransport {
+    mock: MockTransport,
+    versions: Arc<AtomicUsize>,
+}
+
+impl Transport for NoVersionTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        if request.url.serialize_path() == Some("/version".to_string()) {
+            self.versions.fetch_add(1, Ordering::SeqCst);
+            return Ok(TransportResponse{ status: StatusCode::NotFound, headers: Headers::new(), body: "404 page not found".to_string() });
+        }
+
+        return self.mock.send(request);
+    }
+}
+
+#[test]
+fn failed_negotiation_test() {
+    // building fails when the version can't be asked for
+    let built = EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001")
+                                        .transport(Box::new(NoVersionTransport{ mock: MockTransport::new(), versions: Arc::new(AtomicUsize::new(0)) }))
+                                        .build();
+    assert!(built.is_err());
+
+    let versions = Arc::new(AtomicUsize::new(0));
+    let client = EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001")
+                                         .transport(Box::new(NoVersionTransport{ mock: MockTransport::new(), versions: versions.clone() }))
+                                         .negotiate(false)
+                                         .build()
+                                         .unwrap();
+
+    // the requests are sent anyway, and the failure is kept rather than asked again by each of them
+    client.set("/unit/unnegotiated", "one", Some(10)).unwrap();
+    client.refresh("/unit/unnegotiated", 10).unwrap();
+    assert_eq!(client.members_list().unwrap().len(), 1);
+    assert_eq!(versions.load(Ordering::SeqCst), 1);
+
+    // asking for the capabilities asks again
+    assert!(client.capabilities().is_err());
+    assert_eq!(versions.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,665 @@
+use hyper::client::Client;
+use hyper::client::response::Response;
+use hyper::header::{Accept, Connection, ConnectionOption, ContentLength, ContentType, Headers, TransferEncoding, qitem};
+use hyper::method::Method;
+use hyper::mime::{Mime, TopLevel, SubLevel};
+use hyper::net::{ContextVerifier, NetworkConnector, NetworkStream};
+use hyper::status::StatusCode;
+use hyper::version::HttpVersion;
+use hyper::Url;
+use openssl::ssl::{SslContext, SslMethod, SslStream};
+use openssl::ssl::error::{SslError, StreamError};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use etcd::etcd_error::EtcdError;
+use etcd::etcd_tls::EtcdTls;
+
+/// the idle connections kept per member, for each of the request and watch pools
+pub static DEFAULT_MAX_IDLE: usize = 5;
+
+/// Options for the connections of the HyperTransport
+#[derive(Clone, Debug)]
+pub struct ConnectionOptions {
+    /// the idle connections kept per member
+    pub max_idle: usize,
+    /// how long to wait for a member to accept a connection, None waits for the operating system to give up
+    pub connect_timeout: Option<Duration>,
+    /// how long to wait for a member to answer a request, None waits forever
+    pub read_timeout: Option<Duration>,
+    /// how long to wait for an event on a watch, None waits forever
+    pub watch_timeout: Option<Duration>,
+}
+
+impl ConnectionOptions {
+    pub fn new() -> ConnectionOptions {
+        return ConnectionOptions{ max_idle: DEFAULT_MAX_IDLE, connect_timeout: None, read_timeout: None, watch_timeout: None };
+    }
+}
+
+/// The body of a request, the keys api takes forms and the members api takes json
+#[derive(Clone, Copy, Debug)]
+pub enum RequestBody<'a> {
+    /// an application/x-www-form-urlencoded form
+    Form(&'a str),
+    /// an application/json document
+    Json(&'a str),
+}
+
+/// A request of the EtcdClient
+pub struct TransportRequest<'a> {
+    pub method: Method,
+    pub url: &'a Url,
+    pub body: Option<RequestBody<'a>>,
+    /// a watch, etcd holds the response until there is an event
+    pub long_poll: bool,
+    /// cancels the request from another thread
+    pub cancel: Option<&'a CancelToken>,
+}
+
+/// The response to a request, the body has been read in full.
+pub struct TransportResponse {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: String,
+}
+
+/// CancelToken aborts a request from another thread by shutting down its connection, it's used to end watches.
+///
+/// The requests of a token are sent one at a time. Clones cancel the same requests.
+#[derive(Clone)]
+pub struct CancelToken {
+    state: Arc<Mutex<CancelState>>,
+}
+
+struct CancelState {
+    cancelled: bool,
+    /// the connection of the request in flight
+    stream: Option<TcpStream>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        return CancelToken{ state: Arc::new(Mutex::new(CancelState{ cancelled: false, stream: None })) };
+    }
+
+    /// shuts down the connection of the request in flight, and of any request registered from now on
+    pub fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.cancelled = true;
+
+        if let Some(stream) = state.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.state.lock().unwrap().cancelled;
+    }
+
+    /// registers the connection of the next request, it's shut down immediately if the token was already cancelled
+    pub fn register(&self, stream: &TcpStream) -> io::Result<()> {
+        let stream = try!(stream.try_clone());
+        let mut state = self.state.lock().unwrap();
+
+        if state.cancelled {
+            return stream.shutdown(Shutdown::Both);
+        }
+
+        state.stream = Some(stream);
+        return Ok(());
+    }
+}
+
+/// Transport sends the requests of the EtcdClient, the HyperTransport is used unless another is set
+///  with EtcdClientBuilder::transport().
+///
+/// A failure to connect should be returned as an HttpError::HttpIoError, the EtcdClient then fails over
+///  to the next member. A request with a CancelToken should return promptly once it's cancelled.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError>;
+}
+
+/// The default Transport, makes the requests with hyper.
+///
+/// Connections are kept alive and reused across requests from any thread. Watches have pools of their own
+///  so that long polls don't hold on to the connections of normal requests, and their own read timeout.
+pub struct HyperTransport {
+    /// options for connecting to https endpoints
+    tls: EtcdTls,
+    options: ConnectionOptions,
+    /// the idle connections of requests
+    pool: Arc<ConnectionPool>,
+    /// the idle connections of watches
+    watch_pool: Arc<ConnectionPool>,
+}
+
+impl HyperTransport {
+    pub fn new(tls: EtcdTls, options: ConnectionOptions) -> HyperTransport {
+        let max_idle = options.max_idle;
+
+        return HyperTransport{ tls: tls,
+                               options: options,
+                               pool: Arc::new(ConnectionPool::new(max_idle)),
+                               watch_pool: Arc::new(ConnectionPool::new(max_idle)) };
+    }
+
+    /// returns a connector for the host of the url, https connections are configured with the tls options
+    fn connector(&self, url: &Url, long_poll: bool) -> TimeoutConnector {
+        let host = url.serialize_host().unwrap_or(String::new());
+
+        return TimeoutConnector{
+            verifier: if url.scheme == "https" { Some(self.tls.verifier(&host)) } else { None },
+            connect_timeout: self.options.connect_timeout,
+            read_timeout: if long_poll { self.options.watch_timeout } else { self.options.read_timeout },
+        };
+    }
+}
+
+impl Transport for HyperTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        // set once the response is read in full, the connection then goes back to the pool when it's dropped
+        let reusable = Arc::new(AtomicBool::new(false));
+
+        // a cancellable request has a connection of its own, it's closed when the request is cancelled
+        let mut client = match request.cancel {
+            Some(cancel) => Client::with_connector(CancellableConnector{ connector: self.connector(request.url, request.long_poll),
+                                                                        cancel: cancel.clone() }),
+            None => Client::with_connector(PooledConnector{ connector: self.connector(request.url, request.long_poll),
+                                                            pool: if request.long_poll { self.watch_pool.clone() } else { self.pool.clone() },
+                                                            reusable: reusable.clone() }),
+        };
+        let mut builder = client.request(request.method.clone(), request.url.clone())
+                                .header(Accept(vec![qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]));
+        match request.body {
+            Some(RequestBody::Form(body)) => {
+                builder = builder.body(body).header(ContentType(Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, vec![])));
+            },
+            Some(RequestBody::Json(body)) => {
+                builder = builder.body(body).header(ContentType(Mime(TopLevel::Application, SubLevel::Json, vec![])));
+            },
+            None => (),
+        }
+
+        let mut response = try!(builder.send());
+        let mut body = String::new();
+        try!(response.read_to_string(&mut body));
+
+        if is_keep_alive(&response) {
+            reusable.store(true, Ordering::SeqCst);
+        }
+
+        return Ok(TransportResponse{ status: response.status, headers: response.headers.clone(), body: body });
+    }
+}
+
+/// true if the connection can take another request once the body was read, i.e. the server keeps it alive and the
+///  end of the body was told by its length rather than by the connection closing
+fn is_keep_alive(response: &Response) -> bool {
+    let delimited = response.headers.has::<ContentLength>() || response.headers.has::<TransferEncoding>();
+    let keep_alive = match (response.version, response.headers.get::<Connection>()) {
+        (_, Some(&Connection(ref options))) if options.contains(&ConnectionOption::Close) => false,
+        (HttpVersion::Http11, _) => true,
+        (HttpVersion::Http10, Some(&Connection(ref options))) => options.contains(&ConnectionOption::KeepAlive),
+        _ => false,
+    };
+
+    return delimited && keep_alive;
+}
+
+/// the scheme, host and port of a connection, only the connections to the same member over the same scheme, and so
+///  verified the same way, are interchangeable
+type PoolKey = (String, String, u16);
+
+/// The idle connections, shared by the requests of every thread
+///
+/// hyper's Pool only takes back a connection which was read to its end, which a kept alive one never is. The lock is
+///  only held while taking or returning a connection, never while connecting.
+struct ConnectionPool {
+    /// the idle connections kept per member
+    max_idle: usize,
+    idle: Mutex<HashMap<PoolKey, Vec<TimeoutStream>>>,
+}
+
+impl ConnectionPool {
+    fn new(max_idle: usize) -> ConnectionPool {
+        return ConnectionPool{ max_idle: max_idle, idle: Mutex::new(HashMap::new()) };
+    }
+
+    /// an idle connection to the member, the ones the member closed in the meantime are dropped
+    fn checkout(&self, key: &PoolKey) -> Option<TimeoutStream> {
+        let mut idle = self.idle.lock().unwrap();
+        let streams = match idle.get_mut(key) {
+            Some(streams) => streams,
+            None => return None,
+        };
+
+        while let Some(stream) = streams.pop() {
+            if stream.is_idle() {
+                return Some(stream);
+            }
+        }
+
+        return None;
+    }
+
+    fn checkin(&self, key: PoolKey, stream: TimeoutStream) {
+        let mut idle = self.idle.lock().unwrap();
+        let streams = idle.entry(key).or_insert(vec![]);
+
+        if streams.len() < self.max_idle {
+            streams.push(stream);
+        }
+    }
+}
+
+/// takes the connection from the pool, or connects if there is none
+struct PooledConnector {
+    connector: TimeoutConnector,
+    pool: Arc<ConnectionPool>,
+    reusable: Arc<AtomicBool>,
+}
+
+impl NetworkConnector for PooledConnector {
+    type Stream = PooledStream;
+
+    fn connect(&mut self, host: &str, port: u16, scheme: &str) -> io::Result<PooledStream> {
+        let key = (scheme.to_string(), host.to_string(), port);
+        let stream = match self.pool.checkout(&key) {
+            Some(stream) => stream,
+            None => try!(self.connector.connect(host, port, scheme)),
+        };
+
+        return Ok(PooledStream{ stream: Some(stream), key: key, pool: self.pool.clone(), reusable: self.reusable.clone(),
+                                closed: false });
+    }
+}
+
+/// a connection which goes back to the pool when it's dropped, if its response was read in full
+struct PooledStream {
+    stream: Option<TimeoutStream>,
+    key: PoolKey,
+    pool: Arc<ConnectionPool>,
+    reusable: Arc<AtomicBool>,
+    /// hyper closed it, i.e. the server didn't keep it alive
+    closed: bool,
+}
+
+impl PooledStream {
+    fn stream(&mut self) -> &mut TimeoutStream {
+        return self.stream.as_mut().unwrap();
+    }
+}
+
+impl Read for PooledStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return self.stream().read(buf);
+    }
+}
+
+impl Write for PooledStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return self.stream().write(buf);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.stream().flush();
+    }
+}
+
+impl NetworkStream for PooledStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        return self.stream().peer_addr();
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.closed = true;
+        return self.stream().close(how);
+    }
+}
+
+impl Drop for PooledStream {
+    fn drop(&mut self) {
+        if self.closed || !self.reusable.load(Ordering::SeqCst) {
+            return;
+        }
+
+        if let Some(stream) = self.stream.take() {
+            self.pool.checkin(self.key.clone(), stream);
+        }
+    }
+}
+
+/// registers each connection with the CancelToken
+struct CancellableConnector {
+    connector: TimeoutConnector,
+    cancel: CancelToken,
+}
+
+impl NetworkConnector for CancellableConnector {
+    type Stream = TimeoutStream;
+
+    fn connect(&mut self, host: &str, port: u16, scheme: &str) -> io::Result<TimeoutStream> {
+        let stream = try!(self.connector.connect(host, port, scheme));
+        try!(self.cancel.register(stream.tcp()));
+
+        return Ok(stream);
+    }
+}
+
+/// connects like hyper's HttpConnector, with timeouts on the socket
+struct TimeoutConnector {
+    verifier: Option<ContextVerifier>,
+    connect_timeout: Option<Duration>,
+    /// also used for writes
+    read_timeout: Option<Duration>,
+}
+
+impl TimeoutConnector {
+    /// tries each of the addresses of the host in turn
+    fn connect_tcp(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let timeout = match self.connect_timeout {
+            Some(timeout) => timeout,
+            None => return TcpStream::connect((host, port)),
+        };
+
+        let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "no addresses for the host");
+        for addr in try!((host, port).to_socket_addrs()) {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = e,
+            }
+        }
+
+        return Err(last_error);
+    }
+}
+
+impl NetworkConnector for TimeoutConnector {
+    type Stream = TimeoutStream;
+
+    /// nothing has been sent when the connection fails, so the errors are tagged, see is_connect_error()
+    fn connect(&mut self, host: &str, port: u16, scheme: &str) -> io::Result<TimeoutStream> {
+        return self.connect_stream(host, port, scheme).map_err(connect_error);
+    }
+}
+
+impl TimeoutConnector {
+    fn connect_stream(&mut self, host: &str, port: u16, scheme: &str) -> io::Result<TimeoutStream> {
+        let stream = try!(self.connect_tcp(host, port));
+        try!(stream.set_read_timeout(self.read_timeout));
+        try!(stream.set_write_timeout(self.read_timeout));
+
+        return match scheme {
+            "http" => Ok(TimeoutStream::Http(stream)),
+            "https" => {
+                let mut context = try!(SslContext::new(SslMethod::Sslv23).map_err(ssl_to_io_error));
+                if let Some(ref mut verifier) = self.verifier {
+                    verifier(&mut context);
+                }
+
+                Ok(TimeoutStream::Https(try!(SslStream::new(&context, stream).map_err(ssl_to_io_error))))
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported scheme")),
+        };
+    }
+}
+
+/// the connections of the TimeoutConnector
+enum TimeoutStream {
+    Http(TcpStream),
+    Https(SslStream<TcpStream>),
+}
+
+impl TimeoutStream {
+    fn tcp(&self) -> &TcpStream {
+        return match *self {
+            TimeoutStream::Http(ref inner) => inner,
+            TimeoutStream::Https(ref inner) => inner.get_ref(),
+        };
+    }
+
+    /// true if nothing arrived on the idle connection, a member which closed it sent the end of the stream
+    fn is_idle(&self) -> bool {
+        let tcp = self.tcp();
+        if tcp.set_nonblocking(true).is_err() {
+            return false;
+        }
+
+        let mut buf = [0; 1];
+        let idle = match tcp.peek(&mut buf) {
+            Err(ref e) => e.kind() == io::ErrorKind::WouldBlock,
+            Ok(_) => false,
+        };
+
+        return tcp.set_nonblocking(false).is_ok() && idle;
+    }
+}
+
+impl Read for TimeoutStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return match *self {
+            TimeoutStream::Http(ref mut inner) => inner.read(buf),
+            TimeoutStream::Https(ref mut inner) => inner.read(buf),
+        };
+    }
+}
+
+impl Write for TimeoutStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return match *self {
+            TimeoutStream::Http(ref mut inner) => inner.write(buf),
+            TimeoutStream::Https(ref mut inner) => inner.write(buf),
+        };
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return match *self {
+            TimeoutStream::Http(ref mut inner) => inner.flush(),
+            TimeoutStream::Https(ref mut inner) => inner.flush(),
+        };
+    }
+}
+
+impl NetworkStream for TimeoutStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        return match *self {
+            TimeoutStream::Http(ref mut inner) => inner.peer_addr(),
+            TimeoutStream::Https(ref mut inner) => inner.get_mut().peer_addr(),
+        };
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        return match *self {
+            TimeoutStream::Http(ref mut inner) => inner.shutdown(how),
+            TimeoutStream::Https(ref mut inner) => inner.get_mut().shutdown(how),
+        };
+    }
+}
+
+/// An error connecting to a member, including the tls handshake. The request wasn't sent, so even one which isn't
+///  idempotent may be sent to another member.
+#[derive(Debug)]
+struct ConnectError(io::Error);
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "could not connect: {}", self.0);
+    }
+}
+
+impl Error for ConnectError {
+    fn description(&self) -> &str {
+        return "could not connect";
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        return Some(&self.0);
+    }
+}
+
+/// tags the error as having happened while connecting, it keeps its kind so that timeouts can be told apart
+pub fn connect_error(error: io::Error) -> io::Error {
+    if is_connect_error(&error) {
+        return error;
+    }
+
+    return io::Error::new(error.kind(), ConnectError(error));
+}
+
+/// true if the error happened before anything was sent, see connect_error(). A refused connection always is.
+pub fn is_connect_error(error: &io::Error) -> bool {
+    return error.kind() == io::ErrorKind::ConnectionRefused || error.get_ref().map_or(false, |e| e.is::<ConnectError>());
+}
+
+/// true if the tls handshake failed, i.e. the certificate was refused, rather than the connection. Trying again
+///  fails the same way.
+pub fn is_tls_error(error: &io::Error) -> bool {
+    let inner = match error.get_ref() {
+        Some(inner) => inner,
+        None => return false,
+    };
+
+    if let Some(connect_error) = inner.downcast_ref::<ConnectError>() {
+        return is_tls_error(&connect_error.0);
+    }
+
+    return inner.is::<SslError>();
+}
+
+/// the io errors keep their kind, so that timeouts can be told apart
+fn ssl_to_io_error(error: SslError) -> io::Error {
+    return match error {
+        StreamError(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, e),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::Url;
+  use hyper::method::Method;
+  use hyper::net::NetworkConnector;
+  use openssl::crypto::hash::Type;
+  use openssl::ssl::{SslContext, SslMethod, SslStream};
+  use openssl::x509::X509Generator;
+  use std::io::{BufRead, BufReader, Write};
+  use std::net::TcpListener;
+  use std::path::PathBuf;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::thread;
+  use std::time::Duration;
+  use super::{ConnectionOptions, HyperTransport, TimeoutConnector, Transport, TransportRequest};
+  use etcd::etcd_tls::EtcdTls;
+
+  /// accepts the connections with a self signed certificate for localhost, returns the port
+  fn tls_server(connections: usize) -> u16 {
+    let (cert, key) = X509Generator::new().set_bitlength(2048)
+                                          .set_valid_period(1)
+                                          .add_name("CN".to_string(), "localhost".to_string())
+                                          .set_sign_hash(Type::SHA256)
+                                          .generate()
+                                          .unwrap();
+    let mut context = SslContext::new(SslMethod::Sslv23).unwrap();
+    context.set_certificate(&cert).unwrap();
+    context.set_private_key(&key).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+      for stream in listener.incoming().take(connections) {
+        // the handshake fails when the client refuses the certificate
+        let _ = SslStream::accept(&context, stream.unwrap());
+      }
+    });
+
+    return port;
+  }
+
+  fn connect_tls(tls: &EtcdTls, port: u16) -> bool {
+    let mut connector = TimeoutConnector{ verifier: Some(tls.verifier("localhost")),
+                                          connect_timeout: None,
+                                          read_timeout: Some(Duration::from_secs(5)) };
+
+    return connector.connect("127.0.0.1", port, "https").is_ok();
+  }
+
+  #[test]
+  fn missing_ca_file_refused_test() {
+    let port = tls_server(2);
+
+    let mut unverified = EtcdTls::new();
+    unverified.verify = false;
+    assert!(connect_tls(&unverified, port));
+
+    // the ca file can't be loaded, so the server can't be verified
+    let mut missing_ca = EtcdTls::new();
+    missing_ca.ca_file = Some(PathBuf::from("/nonexistent/etcd-rs/ca.pem"));
+    assert!(!connect_tls(&missing_ca, port));
+  }
+
+  /// answers every request on a connection with {}, and counts the connections
+  fn http_server(accepts: Arc<AtomicUsize>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        accepts.fetch_add(1, Ordering::SeqCst);
+        let mut stream = stream.unwrap();
+
+        thread::spawn(move || {
+          let mut reader = BufReader::new(stream.try_clone().unwrap());
+          loop {
+            // the requests have no body, so each ends with an empty line
+            let mut line = String::new();
+            while line != "\r\n" {
+              line.clear();
+              if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+              }
+            }
+
+            if stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}").is_err() {
+              return;
+            }
+          }
+        });
+      }
+    });
+
+    return port;
+  }
+
+  fn get(transport: &HyperTransport, url: &str) -> String {
+    let url = Url::parse(url).unwrap();
+    let request = TransportRequest{ method: Method::Get, url: &url, body: None, long_poll: false, cancel: None };
+
+    return transport.send(&request).unwrap().body;
+  }
+
+  #[test]
+  fn connection_reused_test() {
+    let accepts = Arc::new(AtomicUsize::new(0));
+    let port = http_server(accepts.clone());
+    let transport = HyperTransport::new(EtcdTls::new(), ConnectionOptions::new());
+
+    assert_eq!(get(&transport, &format!("http://127.0.0.1:{}/v2/keys/a", port)), "{}");
+    assert_eq!(get(&transport, &format!("http://127.0.0.1:{}/v2/keys/b", port)), "{}");
+    assert_eq!(accepts.load(Ordering::SeqCst), 1);
+
+    // an https connection to the same host and port isn't one of them
+    let key = ("https".to_string(), "127.0.0.1".to_string(), port);
+    assert!(transport.pool.checkout(&key).is_none());
+    assert!(transport.pool.checkout(&("http".to_string(), "127.0.0.1".to_string(), port)).is_some());
+  }
+
+}
@@ -1,6 +1,9 @@
 extern crate rustc_serialize;
 //extern crate chrono;
 extern crate hyper;
+extern crate openssl;
+extern crate rand;
+extern crate time;
 extern crate url;
 
 
@@ -8,6 +11,8 @@ extern crate url;
 extern crate log;
 
 pub mod etcd;
+pub mod recipes;
+pub mod testing;
 
 #[cfg(test)]
 mod tests;
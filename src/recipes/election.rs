@@ -0,0 +1,286 @@
+use std::time::Instant;
+use etcd::{AtomicOp, EtcdClient};
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_result::{CompareResult, EtcdResult};
+use etcd::etcd_watcher::{is_timeout, Watcher};
+use recipes::keep_alive::KeepAlive;
+use recipes::required_node;
+
+/// Election chooses a single leader among the candidates for the leader key.
+///
+/// A candidate becomes the leader by creating the key with its name as the value, which only succeeds if there is no
+///  leader. The ttl of the key is refreshed while the candidate leads, so another is elected within ttl seconds if
+///  the leader dies. Resigning, or dropping the Election, removes the key if this candidate still holds it.
+///
+/// ```no_run
+/// use etcd_rs::etcd::EtcdClientBuilder;
+/// use etcd_rs::recipes::election::Election;
+///
+/// let client = EtcdClientBuilder::new().endpoint("http://localhost:4001").build().unwrap();
+/// let mut election = Election::new(&client, "elections/scheduler", "scheduler-1", 10);
+///
+/// election.campaign().unwrap();
+/// while election.is_leader() {
+///     // schedule
+/// }
+/// ```
+pub struct Election {
+    client: EtcdClient,
+    key: String,
+    name: String,
+    ttl: u64,
+    /// refreshes the key while this candidate leads
+    term: Option<KeepAlive>,
+}
+
+impl Election {
+    /// name identifies this candidate, it must be unique among the candidates
+    pub fn new(client: &EtcdClient, key: &str, name: &str, ttl: u64) -> Election {
+        return Election{ client: client.clone(), key: key.to_string(), name: name.to_string(), ttl: ttl, term: None };
+    }
+
+    /// blocks until this candidate is the leader
+    pub fn campaign(&mut self) -> Result<(), EtcdError> {
+        loop {
+            let index = match try!(self.try_campaign_at()) {
+                None => return Ok(()),
+                Some(index) => index,
+            };
+
+            // wait for the leader to change, it's most likely the key was removed or expired
+            loop {
+                match self.client.watch(&self.key, Some(index + 1), false) {
+                    Ok(..) => break,
+                    Err(EtcdError::Api{ code: EtcdErrorCode::EventIndexCleared, .. }) => break,
+                    // the leader is still leading
+                    Err(ref e) if is_timeout(e) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// becomes the leader only if there is none, true if this candidate is the leader
+    pub fn try_campaign(&mut self) -> Result<bool, EtcdError> {
+        return self.try_campaign_at().map(|index| index.is_none());
+    }
+
+    /// None if this candidate is the leader, otherwise the etcd index at which another was
+    fn try_campaign_at(&mut self) -> Result<Option<u64>, EtcdError> {
+        if self.is_leader() {
+            return Ok(None);
+        }
+
+        let created = Instant::now();
+        return match self.client.create(&self.key, &self.name, Some(self.ttl)) {
+            Ok(node) => {
+                let node = try!(required_node(node));
+                info!("{} is the leader of {}", self.name, self.key);
+
+                self.term = Some(KeepAlive::new(self.client.clone(), &self.key, self.ttl, Some(&self.name as &str),
+                                                node.modified_index as u64, created));
+                Ok(None)
+            },
+            Err(EtcdError::Api{ code: EtcdErrorCode::NodeExist, index, .. }) => Ok(Some(index)),
+            Err(e) => Err(e),
+        };
+    }
+
+    /// true while this candidate leads, false once it resigned or the key expired before it could be refreshed. The
+    ///  key is treated as expired once the ttl has passed since the last successful refresh, even if etcd couldn't be
+    ///  reached to say so, as another candidate may have been elected by then.
+    pub fn is_leader(&self) -> bool {
+        return self.term.as_ref().map_or(false, |term| term.is_alive());
+    }
+
+    /// the name of the current leader, None if there is none
+    pub fn leader(&self) -> Result<Option<String>, EtcdError> {
+        return match self.client.get(&self.key) {
+            Ok(node) => Ok(try!(required_node(node)).value),
+            Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, .. }) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+
+    /// stops leading, the key is removed only if it's still the one this candidate created and refreshed
+    pub fn resign(&mut self) -> Result<(), EtcdError> {
+        let mut term = match self.term.take() {
+            Some(term) => term,
+            None => return Ok(()),
+        };
+
+        term.stop();
+
+        return match self.client.compare_and_delete(&self.key, &[AtomicOp::PrevIndex(term.modified_index())]) {
+            Ok(CompareResult::Success(..)) => {
+                info!("{} resigned as the leader of {}", self.name, self.key);
+                Ok(())
+            },
+            Ok(CompareResult::Failed{ .. }) => Ok(()),
+            Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, .. }) => Ok(()),
+            Err(e) => Err(e),
+        };
+    }
+
+    /// watches for changes of the leader, see Observer
+    pub fn observe<'a>(&'a self) -> Observer<'a> {
+        return Observer{ election: self, watcher: None, leader: None };
+    }
+}
+
+impl Drop for Election {
+    fn drop(&mut self) {
+        if let Err(e) = self.resign() {
+            warn!("could not resign as the leader of {}, it expires with its ttl: {:?}", self.key, e);
+        }
+    }
+}
+
+/// Observer iterates over the leaders of an election, see Election::observe
+///
+/// The first item is the current leader, each item after is the next change. None means there is no leader.
+pub struct Observer<'a> {
+    election: &'a Election,
+    watcher: Option<Watcher<'a>>,
+    /// the last leader returned
+    leader: Option<Option<String>>,
+}
+
+impl<'a> Observer<'a> {
+    /// the leader after the event, refreshes are not seen by watches so every event is a change of the key
+    fn leader_of(event: EtcdResult) -> Option<String> {
+        return match &event.action as &str {
+            "delete" | "expire" | "compareAndDelete" => None,
+            _ => event.node.and_then(|node| node.value),
+        };
+    }
+
+    /// reads the current leader and starts watching from there
+    fn start(&mut self) -> Result<Option<String>, EtcdError> {
+        let election: &'a Election = self.election;
+
+        let (leader, index) = match election.client.get(&election.key) {
+            Ok(node) => {
+                let node = try!(required_node(node));
+                (node.value, node.modified_index as u64)
+            },
+            Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, index, .. }) => (None, index),
+            Err(e) => return Err(e),
+        };
+
+        self.watcher = Some(election.client.watcher(&election.key, Some(index + 1), false));
+        return Ok(leader);
+    }
+}
+
+impl<'a> Iterator for Observer<'a> {
+    type Item = Result<Option<String>, EtcdError>;
+
+    fn next(&mut self) -> Option<Result<Option<String>, EtcdError>> {
+        if self.watcher.is_none() {
+            let leader = self.start();
+            if let Ok(ref leader) = leader {
+                self.leader = Some(leader.clone());
+            }
+
+            return Some(leader);
+        }
+
+        loop {
+            let leader = match self.watcher.as_mut().unwrap().next() {
+                Some(Ok(event)) => Observer::leader_of(event),
+                Some(Err(e)) => return Some(Err(e)),
+                None => return None,
+            };
+
+            // a new term of the same leader isn't a change
+            if self.leader.as_ref() != Some(&leader) {
+                self.leader = Some(leader.clone());
+                return Some(Ok(leader));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc::channel;
+  use std::thread;
+  use std::time::Duration;
+  use super::Election;
+  use recipes::keep_alive::tests::unreachable_refresh_client;
+  use recipes::tests::timeout_watch_client;
+  use testing::MockTransport;
+
+  #[test]
+  fn campaign_resign_test() {
+    let client = MockTransport::new().client();
+    let mut first = Election::new(&client, "elections/test", "first", 10);
+    let mut second = Election::new(&client, "elections/test", "second", 10);
+
+    assert!(first.try_campaign().unwrap());
+    assert!(first.is_leader());
+    assert!(!second.try_campaign().unwrap());
+    assert_eq!(second.leader().unwrap(), Some("first".to_string()));
+
+    first.resign().unwrap();
+    assert!(!first.is_leader());
+    assert_eq!(second.leader().unwrap(), None);
+
+    second.campaign().unwrap();
+    assert!(second.is_leader());
+  }
+
+  #[test]
+  fn observe_test() {
+    let client = MockTransport::new().client();
+    let mut first = Election::new(&client, "elections/test", "first", 10);
+    let mut second = Election::new(&client, "elections/test", "second", 10);
+    let observer = Election::new(&client, "elections/test", "observer", 10);
+
+    first.campaign().unwrap();
+    let mut leaders = observer.observe();
+    assert_eq!(leaders.next().unwrap().unwrap(), Some("first".to_string()));
+
+    first.resign().unwrap();
+    assert_eq!(leaders.next().unwrap().unwrap(), None);
+
+    second.campaign().unwrap();
+    assert_eq!(leaders.next().unwrap().unwrap(), Some("second".to_string()));
+  }
+
+  #[test]
+  fn campaign_watch_timeout_test() {
+    let mock = MockTransport::new();
+    let mut first = Election::new(&mock.client(), "elections/timeout", "first", 10);
+    assert!(first.try_campaign().unwrap());
+
+    let (sender, elected) = channel();
+    let second_client = timeout_watch_client(&mock, 2);
+    let candidate = thread::spawn(move || {
+      sender.send(Election::new(&second_client, "elections/timeout", "second", 10).campaign().is_ok()).unwrap();
+    });
+
+    // the watches which timed out are reissued, the candidate still waits for the leader
+    thread::sleep(Duration::from_millis(200));
+    assert!(elected.try_recv().is_err());
+
+    first.resign().unwrap();
+    assert!(elected.recv().unwrap());
+    candidate.join().unwrap();
+  }
+
+  #[test]
+  fn unreachable_leader_test() {
+    let client = unreachable_refresh_client();
+    let mut first = Election::new(&client, "elections/unreachable", "first", 1);
+    let mut second = Election::new(&client, "elections/unreachable", "second", 1);
+
+    assert!(first.try_campaign().unwrap());
+
+    // the leader can't refresh its key, it stops leading before another can be elected
+    thread::sleep(Duration::from_millis(1200));
+    assert!(!first.is_leader());
+    assert!(second.try_campaign().unwrap());
+  }
+}
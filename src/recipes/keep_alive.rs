@@ -0,0 +1,182 @@
+use std::cmp;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use etcd::{AtomicOp, EtcdClient};
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_node::EtcdNode;
+use etcd::etcd_result::CompareResult;
+
+/// KeepAlive refreshes the ttl of a key from a thread of its own, until it's stopped or the key is gone.
+///
+/// The key is refreshed every third of the ttl, so that two refreshes can fail before it expires. If a value is
+///  given the key is only refreshed while it still has that value, so a key taken over by another isn't kept alive.
+///
+/// The key is only known to live for the ttl after the last successful refresh. Once that has passed without another,
+///  whatever the refreshes failed with, the key is treated as gone even if etcd couldn't be reached to say so.
+pub struct KeepAlive {
+    state: Arc<(Mutex<KeepAliveState>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct KeepAliveState {
+    stopped: bool,
+    /// the key expired, or was removed or changed by another client
+    lost: bool,
+    /// refreshes modify the key, this is the index of the last
+    modified_index: u64,
+    /// the key expires at the latest this long after the last successful refresh was sent
+    expires: Instant,
+}
+
+impl KeepAlive {
+    /// modified_index is that of the key as it was created, and created when the request which created it was sent
+    pub fn new(client: EtcdClient, key: &str, ttl: u64, value: Option<&str>, modified_index: u64, created: Instant) -> KeepAlive {
+        let lease = Duration::from_secs(ttl);
+        let state = Arc::new((Mutex::new(KeepAliveState{ stopped: false, lost: false, modified_index: modified_index,
+                                                         expires: created + lease }),
+                              Condvar::new()));
+        let thread_state = state.clone();
+        let key = key.to_string();
+        let value = value.map(|v| v.to_string());
+        let interval = Duration::from_millis(cmp::max(ttl * 1000 / 3, 1));
+
+        let thread = thread::spawn(move || {
+            let &(ref lock, ref stop) = &*thread_state;
+
+            loop {
+                {
+                    let mut state = lock.lock().unwrap();
+                    if !state.stopped {
+                        state = stop.wait_timeout(state, interval).unwrap().0;
+                    }
+
+                    if state.stopped {
+                        return;
+                    }
+                }
+
+                // the ttl restarts at the latest when etcd receives the refresh
+                let sent = Instant::now();
+
+                match KeepAlive::refresh(&client, &key, ttl, value.as_ref().map(|v| v as &str)) {
+                    Ok(Some(node)) => {
+                        debug!("refreshed {}", key);
+                        let mut state = lock.lock().unwrap();
+                        state.modified_index = node.modified_index as u64;
+                        state.expires = sent + lease;
+                    },
+                    Ok(None) | Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, .. }) => {
+                        warn!("{} expired or was taken over before it could be refreshed", key);
+                        lock.lock().unwrap().lost = true;
+                        return;
+                    },
+                    Err(e) => {
+                        let mut state = lock.lock().unwrap();
+                        if Instant::now() >= state.expires {
+                            warn!("could not refresh {} within its ttl, it has expired: {:?}", key, e);
+                            state.lost = true;
+                            return;
+                        }
+
+                        warn!("could not refresh {}, retrying: {:?}", key, e);
+                    },
+                }
+            }
+        });
+
+        return KeepAlive{ state: state, thread: Some(thread) };
+    }
+
+    /// the refreshed node, None if the key no longer has the value
+    fn refresh(client: &EtcdClient, key: &str, ttl: u64, value: Option<&str>) -> Result<Option<EtcdNode>, EtcdError> {
+        let value = match value {
+            Some(value) => value,
+            None => return client.refresh(key, ttl),
+        };
+
+        return match try!(client.compare_and_refresh(key, ttl, &[AtomicOp::PrevValue(value)])) {
+            CompareResult::Success(result) => Ok(result.node),
+            CompareResult::Failed{ .. } => Ok(None),
+        };
+    }
+
+    /// false once the key is known to be gone, or the ttl has passed since the last successful refresh
+    pub fn is_alive(&self) -> bool {
+        let state = (self.state.0).lock().unwrap();
+        return !state.lost && Instant::now() < state.expires;
+    }
+
+    /// the modified_index of the key after the last refresh
+    pub fn modified_index(&self) -> u64 {
+        return (self.state.0).lock().unwrap().modified_index;
+    }
+
+    /// stops refreshing the key, waiting for a refresh in flight
+    pub fn stop(&mut self) {
+        (self.state.0).lock().unwrap().stopped = true;
+        self.state.1.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use hyper::error::HttpError;
+  use std::io;
+  use std::thread;
+  use std::time::{Duration, Instant};
+  use super::KeepAlive;
+  use etcd::{EtcdClient, EtcdClientBuilder};
+  use etcd::etcd_error::EtcdError;
+  use etcd::transport::{RequestBody, Transport, TransportRequest, TransportResponse};
+  use testing::MockTransport;
+
+  /// etcd can't be reached to refresh the keys, the rest are answered by the mock
+  struct UnreachableRefreshTransport {
+    mock: MockTransport,
+  }
+
+  impl Transport for UnreachableRefreshTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+      return match request.body {
+        Some(RequestBody::Form(body)) if body.contains("refresh=true") => {
+          Err(EtcdError::from(HttpError::HttpIoError(io::Error::new(io::ErrorKind::TimedOut, "unreachable"))))
+        },
+        _ => self.mock.send(request),
+      };
+    }
+  }
+
+  /// a client whose refreshes time out, as if etcd became unreachable once the keys were created
+  pub fn unreachable_refresh_client() -> EtcdClient {
+    return EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001")
+                                   .transport(Box::new(UnreachableRefreshTransport{ mock: MockTransport::new() }))
+                                   .build()
+                                   .unwrap();
+  }
+
+  #[test]
+  fn expires_unreachable_test() {
+    let client = unreachable_refresh_client();
+    let created = Instant::now();
+    let node = client.create("/keep_alive/unreachable", "one", Some(1)).unwrap().unwrap();
+
+    let keep_alive = KeepAlive::new(client.clone(), &node.key, 1, Some("one"), node.modified_index as u64, created);
+    assert!(keep_alive.is_alive());
+
+    // the refreshes fail with timeouts rather than KeyNotFound, the key is still gone once its ttl has passed
+    thread::sleep(Duration::from_millis(1200));
+    assert!(!keep_alive.is_alive());
+  }
+}
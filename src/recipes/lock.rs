@@ -0,0 +1,227 @@
+use std::time::Instant;
+use etcd::EtcdClient;
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_node::EtcdNode;
+use etcd::etcd_watcher::is_timeout;
+use recipes::keep_alive::KeepAlive;
+use recipes::required_node;
+
+/// Lock is a distributed mutex, the clients waiting for it are queued in the order they asked for it.
+///
+/// Each acquisition appends an in-order key with a ttl to the lock directory, the lowest key holds the lock and each
+///  of the others waits for the deletion of the key before its own. The ttl of the key is refreshed until it's
+///  released, so the lock is freed within ttl seconds if the holder dies.
+///
+/// ```no_run
+/// use etcd_rs::etcd::EtcdClientBuilder;
+/// use etcd_rs::recipes::lock::Lock;
+///
+/// let client = EtcdClientBuilder::new().endpoint("http://localhost:4001").build().unwrap();
+/// let lock = Lock::new(&client, "locks/jobs", 10);
+///
+/// let guard = lock.acquire().unwrap();
+/// // pass guard.token() with each write to the protected resource
+/// ```
+pub struct Lock {
+    client: EtcdClient,
+    dir: String,
+    ttl: u64,
+}
+
+impl Lock {
+    /// the lock is held by the keys in dir, which expire after ttl seconds without a refresh
+    pub fn new(client: &EtcdClient, dir: &str, ttl: u64) -> Lock {
+        return Lock{ client: client.clone(), dir: dir.to_string(), ttl: ttl };
+    }
+
+    /// blocks until the lock is held
+    pub fn acquire(&self) -> Result<LockGuard, EtcdError> {
+        return self.acquire_or_wait(true).map(|guard| guard.unwrap());
+    }
+
+    /// acquires the lock only if it's free, None if it's held by another
+    pub fn try_acquire(&self) -> Result<Option<LockGuard>, EtcdError> {
+        return self.acquire_or_wait(false);
+    }
+
+    fn acquire_or_wait(&self, wait: bool) -> Result<Option<LockGuard>, EtcdError> {
+        let created = Instant::now();
+        let node = try!(required_node(try!(self.client.create_in_order(&self.dir, "", Some(self.ttl)))));
+
+        // from here on the key is removed when the guard is dropped, including on any error
+        let guard = LockGuard{ client: self.client.clone(),
+                               key: node.key.clone(),
+                               token: node.created_index as u64,
+                               keep_alive: KeepAlive::new(self.client.clone(), &node.key, self.ttl, None, node.modified_index as u64,
+                                                          created),
+                               released: false };
+
+        loop {
+            let waiters: Vec<EtcdNode> = try!(required_node(try!(self.client.index_list(&self.dir)))).nodes.unwrap_or(vec![]);
+
+            let predecessor = match waiters.iter().position(|n| n.key == guard.key) {
+                Some(0) => return Ok(Some(guard)),
+                Some(position) => &waiters[position - 1],
+                None => {
+                    return Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound,
+                                               message: "the lock key expired while waiting".to_string(),
+                                               cause: Some(guard.key.clone()),
+                                               index: 0 });
+                },
+            };
+
+            if !wait {
+                return Ok(None);
+            }
+
+            // the wait index catches the deletion even if it happened since the list, then the waiters are listed
+            //  again as the predecessor may have died rather than held the lock
+            debug!("{} is waiting for {}", guard.key, predecessor.key);
+            loop {
+                match self.client.watch(&predecessor.key, Some(predecessor.modified_index as u64 + 1), false) {
+                    Ok(..) => break,
+                    Err(EtcdError::Api{ code: EtcdErrorCode::EventIndexCleared, .. }) => break,
+                    // the predecessor still holds the lock, or waits for it
+                    Err(ref e) if is_timeout(e) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+/// LockGuard holds the lock until it's released or dropped
+pub struct LockGuard {
+    client: EtcdClient,
+    key: String,
+    token: u64,
+    keep_alive: KeepAlive,
+    released: bool,
+}
+
+impl LockGuard {
+    /// the fencing token, the created_index of the lock key. It increases with each acquisition, so stores which
+    ///  reject writes with a lower token than they have seen are safe from a holder which lost the lock unawares.
+    pub fn token(&self) -> u64 {
+        return self.token;
+    }
+
+    /// the key of this holder in the lock directory
+    pub fn key(&self) -> &str {
+        return &self.key;
+    }
+
+    /// false if the key expired, i.e. this client couldn't reach etcd for the ttl, and another may hold the lock
+    pub fn is_held(&self) -> bool {
+        return self.keep_alive.is_alive();
+    }
+
+    /// releases the lock, returning any error from removing the key
+    pub fn release(mut self) -> Result<(), EtcdError> {
+        self.released = true;
+        return self.remove();
+    }
+
+    fn remove(&mut self) -> Result<(), EtcdError> {
+        self.keep_alive.stop();
+
+        return match self.client.remove(&self.key) {
+            Ok(..) => Ok(()),
+            Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, .. }) => Ok(()),
+            Err(e) => Err(e),
+        };
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+
+        if let Err(e) = self.remove() {
+            warn!("could not release the lock {}, it expires with its ttl: {:?}", self.key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc::channel;
+  use std::thread;
+  use std::time::Duration;
+  use super::Lock;
+  use recipes::keep_alive::tests::unreachable_refresh_client;
+  use recipes::tests::timeout_watch_client;
+  use testing::MockTransport;
+
+  #[test]
+  fn try_acquire_test() {
+    let client = MockTransport::new().client();
+    let lock = Lock::new(&client, "locks/test", 10);
+
+    let guard = lock.try_acquire().unwrap().unwrap();
+    assert!(guard.is_held());
+    assert!(lock.try_acquire().unwrap().is_none());
+
+    let token = guard.token();
+    guard.release().unwrap();
+
+    let guard = lock.try_acquire().unwrap().unwrap();
+    assert!(guard.token() > token);
+  }
+
+  #[test]
+  fn acquire_waits_test() {
+    let client = MockTransport::new().client();
+    let lock = Lock::new(&client, "locks/test", 10);
+    let guard = lock.acquire().unwrap();
+
+    let (sender, acquired) = channel();
+    let waiter_client = client.clone();
+    let waiter = thread::spawn(move || {
+      let token = Lock::new(&waiter_client, "locks/test", 10).acquire().unwrap().token();
+      sender.send(token).unwrap();
+    });
+
+    thread::sleep(Duration::from_millis(200));
+    assert!(acquired.try_recv().is_err());
+
+    let token = guard.token();
+    drop(guard);
+
+    assert!(acquired.recv().unwrap() > token);
+    waiter.join().unwrap();
+  }
+
+  #[test]
+  fn acquire_watch_timeout_test() {
+    let mock = MockTransport::new();
+    let guard = Lock::new(&mock.client(), "locks/timeout", 10).acquire().unwrap();
+
+    let (sender, acquired) = channel();
+    let waiter_client = timeout_watch_client(&mock, 2);
+    let waiter = thread::spawn(move || {
+      sender.send(Lock::new(&waiter_client, "locks/timeout", 10).acquire().is_ok()).unwrap();
+    });
+
+    // the watches which timed out are reissued, the waiter still waits for the holder
+    thread::sleep(Duration::from_millis(200));
+    assert!(acquired.try_recv().is_err());
+
+    drop(guard);
+    assert!(acquired.recv().unwrap());
+    waiter.join().unwrap();
+  }
+
+  #[test]
+  fn unreachable_lost_test() {
+    let client = unreachable_refresh_client();
+    let guard = Lock::new(&client, "locks/unreachable", 1).acquire().unwrap();
+    assert!(guard.is_held());
+
+    // the refreshes fail with timeouts rather than KeyNotFound, the lock is still lost once its ttl has passed
+    thread::sleep(Duration::from_millis(1200));
+    assert!(!guard.is_held());
+  }
+}
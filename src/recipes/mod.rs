@@ -0,0 +1,48 @@
+//! Coordination recipes built on the etcd v2 keys api.
+
+pub mod election;
+pub mod lock;
+pub mod queue;
+mod keep_alive;
+
+use etcd::etcd_error::EtcdError;
+use etcd::etcd_node::EtcdNode;
+
+/// the node of a successful request, etcd always returns one
+fn required_node(node: Option<EtcdNode>) -> Result<EtcdNode, EtcdError> {
+    return node.ok_or(EtcdError::MissingField{ field: "node".to_string(), key: None });
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::header::Headers;
+  use hyper::status::StatusCode;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use etcd::{EtcdClient, EtcdClientBuilder};
+  use etcd::etcd_error::EtcdError;
+  use etcd::transport::{Transport, TransportRequest, TransportResponse};
+  use testing::MockTransport;
+
+  /// answers the first watches with the empty body etcd sends once a watch times out, the rest are answered by the mock
+  struct TimeoutWatchTransport {
+    mock: MockTransport,
+    timeouts: usize,
+    watches: AtomicUsize,
+  }
+
+  impl Transport for TimeoutWatchTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+      if request.long_poll && self.watches.fetch_add(1, Ordering::SeqCst) < self.timeouts {
+        return Ok(TransportResponse{ status: StatusCode::Ok, headers: Headers::new(), body: String::new() });
+      }
+
+      return self.mock.send(request);
+    }
+  }
+
+  /// a client of the mock whose first watches time out without an event
+  pub fn timeout_watch_client(mock: &MockTransport, timeouts: usize) -> EtcdClient {
+    let transport = TimeoutWatchTransport{ mock: mock.clone(), timeouts: timeouts, watches: AtomicUsize::new(0) };
+    return EtcdClientBuilder::new().endpoint("http://10.0.0.1:4001").transport(Box::new(transport)).build().unwrap();
+  }
+}
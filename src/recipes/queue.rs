@@ -0,0 +1,285 @@
+use etcd::{AtomicOp, EtcdClient};
+use etcd::etcd_error::{EtcdError, EtcdErrorCode};
+use etcd::etcd_node::EtcdNode;
+use etcd::etcd_result::CompareResult;
+use etcd::etcd_watcher::is_timeout;
+use recipes::required_node;
+
+/// the directory of the claims, within the queue directory. etcd hides keys beginning with _ from listings and
+///  recursive watches, so it mustn't be one.
+static INFLIGHT_DIR: &'static str = "inflight";
+
+/// Queue is a first in first out queue shared by any number of producers and consumers.
+///
+/// Items are in-order keys in the queue directory. Items can be claimed for a visibility timeout: a claim is a key
+///  with a ttl in the inflight directory within the queue directory, created only if there is none, so each item is
+///  claimed by one consumer at a time. The item stays in the queue hidden from the other consumers until it's
+///  acknowledged, and reappears if the consumer dies and the claim expires. Claimed items are delivered at least once.
+///
+/// A consumer pops the lowest unclaimed item by deleting it on the condition that it's unchanged since it was listed,
+///  so each item is popped once. An item claimed between the list and the delete is popped as well as delivered to
+///  its claim.
+///
+/// ```no_run
+/// use etcd_rs::etcd::EtcdClientBuilder;
+/// use etcd_rs::recipes::queue::Queue;
+///
+/// let client = EtcdClientBuilder::new().endpoint("http://localhost:4001").build().unwrap();
+/// let queue = Queue::new(&client, "queues/jobs");
+///
+/// queue.push("job1").unwrap();
+///
+/// let claim = queue.claim(30).unwrap();
+/// // process claim.value()
+/// claim.ack().unwrap();
+/// ```
+pub struct Queue {
+    client: EtcdClient,
+    dir: String,
+}
+
+impl Queue {
+    pub fn new(client: &EtcdClient, dir: &str) -> Queue {
+        return Queue{ client: client.clone(), dir: dir.to_string() };
+    }
+
+    /// adds the item to the back of the queue, returns its key
+    pub fn push(&self, value: &str) -> Result<String, EtcdError> {
+        let node = try!(required_node(try!(self.client.create_in_order(&self.dir, value, None))));
+        return Ok(node.key);
+    }
+
+    /// removes the item at the front of the queue, blocking until there is one
+    pub fn pop(&self) -> Result<String, EtcdError> {
+        loop {
+            let (items, index) = try!(self.available());
+
+            for item in items {
+                // another consumer may have popped it since the list
+                match self.client.compare_and_delete(&item.key, &[AtomicOp::PrevIndex(item.modified_index as u64)]) {
+                    Ok(CompareResult::Success(..)) => return Ok(item.value.unwrap_or(String::new())),
+                    Ok(CompareResult::Failed{ .. }) => continue,
+                    Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, .. }) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            try!(self.wait(index));
+        }
+    }
+
+    /// claims the item at the front of the queue for visibility_timeout seconds, blocking until there is one
+    pub fn claim(&self, visibility_timeout: u64) -> Result<Claim, EtcdError> {
+        loop {
+            let (items, index) = try!(self.available());
+
+            for item in items {
+                let claim_key = self.claim_key(&item);
+                let value = item.value.unwrap_or(String::new());
+
+                // another consumer may have claimed it since the list
+                match self.client.create(&claim_key, &value, Some(visibility_timeout)) {
+                    Ok(..) => (),
+                    Err(EtcdError::Api{ code: EtcdErrorCode::NodeExist, .. }) => continue,
+                    Err(e) => return Err(e),
+                }
+
+                let claim = Claim{ client: self.client.clone(), item_key: item.key, claim_key: claim_key, value: value,
+                                   done: false };
+
+                // the item may have been popped since the list, then dropping the claim removes it
+                match self.client.get(&claim.item_key) {
+                    Ok(..) => return Ok(claim),
+                    Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, .. }) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            try!(self.wait(index));
+        }
+    }
+
+    /// the value of the item at the front of the queue, None if the queue is empty
+    pub fn peek(&self) -> Result<Option<String>, EtcdError> {
+        let (items, _) = try!(self.available());
+        return Ok(items.into_iter().next().map(|item| item.value.unwrap_or(String::new())));
+    }
+
+    /// the number of items waiting, claimed items are not counted
+    pub fn len(&self) -> Result<usize, EtcdError> {
+        let (items, _) = try!(self.available());
+        return Ok(items.len());
+    }
+
+    /// the unclaimed items in order, and the etcd index they were read at
+    fn available(&self) -> Result<(Vec<EtcdNode>, u64), EtcdError> {
+        let result = match self.client.get_result(&self.dir, true, true) {
+            Ok(result) => result,
+            Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, index, .. }) => return Ok((vec![], index)),
+            Err(e) => return Err(e),
+        };
+
+        let nodes = try!(required_node(result.node)).nodes.unwrap_or(vec![]);
+        let inflight_dir = format!("/{}/{}", self.dir.trim_matches('/'), INFLIGHT_DIR);
+
+        // the claims are named for the items
+        let claimed: Vec<String> = nodes.iter()
+                                        .filter(|n| n.dir && n.key == inflight_dir)
+                                        .flat_map(|n| n.nodes.iter().flat_map(|claims| claims.iter()))
+                                        .map(|claim| claim.key.rsplit('/').next().unwrap_or("").to_string())
+                                        .collect();
+
+        let items = nodes.into_iter()
+                         .filter(|n| !n.dir && !claimed.iter().any(|name| n.key.ends_with(&format!("/{}", name))))
+                         .collect();
+
+        return Ok((items, result.x_etcd_index));
+    }
+
+    fn claim_key(&self, item: &EtcdNode) -> String {
+        let name = item.key.rsplit('/').next().unwrap_or("");
+        return format!("{}/{}/{}", self.dir, INFLIGHT_DIR, name);
+    }
+
+    /// blocks until the queue changes after the index, i.e. an item is pushed or a claim is released or expires. It
+    ///  also returns once the watch times out, the queue is then listed again.
+    fn wait(&self, index: u64) -> Result<(), EtcdError> {
+        return match self.client.watch(&self.dir, Some(index + 1), true) {
+            Ok(..) => Ok(()),
+            Err(EtcdError::Api{ code: EtcdErrorCode::EventIndexCleared, .. }) => Ok(()),
+            Err(ref e) if is_timeout(e) => Ok(()),
+            Err(e) => Err(e),
+        };
+    }
+}
+
+/// Claim is an item taken from a Queue for a visibility timeout, see Queue::claim
+///
+/// Dropping the claim without acknowledging it releases the item back to the queue.
+pub struct Claim {
+    client: EtcdClient,
+    item_key: String,
+    claim_key: String,
+    value: String,
+    /// acknowledged or released
+    done: bool,
+}
+
+impl Claim {
+    pub fn value(&self) -> &str {
+        return &self.value;
+    }
+
+    /// removes the item from the queue, it was processed
+    pub fn ack(mut self) -> Result<(), EtcdError> {
+        self.done = true;
+
+        try!(ignore_not_found(self.client.remove(&self.item_key)));
+        return ignore_not_found(self.client.remove(&self.claim_key));
+    }
+
+    /// returns the item to the queue for another consumer
+    pub fn release(mut self) -> Result<(), EtcdError> {
+        self.done = true;
+        return ignore_not_found(self.client.remove(&self.claim_key));
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        if let Err(e) = ignore_not_found(self.client.remove(&self.claim_key)) {
+            warn!("could not release {}, it reappears when the claim expires: {:?}", self.item_key, e);
+        }
+    }
+}
+
+/// the key was already removed, i.e. the claim expired
+fn ignore_not_found(result: Result<Option<EtcdNode>, EtcdError>) -> Result<(), EtcdError> {
+    return match result {
+        Ok(..) => Ok(()),
+        Err(EtcdError::Api{ code: EtcdErrorCode::KeyNotFound, .. }) => Ok(()),
+        Err(e) => Err(e),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc::channel;
+  use std::thread;
+  use std::time::Duration;
+  use super::Queue;
+  use recipes::tests::timeout_watch_client;
+  use testing::MockTransport;
+
+  #[test]
+  fn push_pop_test() {
+    let client = MockTransport::new().client();
+    let queue = Queue::new(&client, "queues/test");
+
+    assert_eq!(queue.len().unwrap(), 0);
+    assert_eq!(queue.peek().unwrap(), None);
+
+    queue.push("first").unwrap();
+    queue.push("second").unwrap();
+
+    assert_eq!(queue.len().unwrap(), 2);
+    assert_eq!(queue.peek().unwrap(), Some("first".to_string()));
+    assert_eq!(queue.pop().unwrap(), "first");
+    assert_eq!(queue.pop().unwrap(), "second");
+    assert_eq!(queue.len().unwrap(), 0);
+
+    // the items were deleted without being claimed
+    assert!(client.get("/queues/test/inflight").is_err());
+  }
+
+  #[test]
+  fn claim_test() {
+    let client = MockTransport::new().client();
+    let queue = Queue::new(&client, "queues/test");
+
+    queue.push("first").unwrap();
+    queue.push("second").unwrap();
+
+    // the claimed item is hidden from the other consumers until it's released
+    let claim = queue.claim(30).unwrap();
+    assert_eq!(claim.value(), "first");
+    assert_eq!(queue.peek().unwrap(), Some("second".to_string()));
+
+    // nor is it popped
+    assert_eq!(queue.pop().unwrap(), "second");
+    queue.push("third").unwrap();
+
+    claim.release().unwrap();
+    assert_eq!(queue.len().unwrap(), 2);
+
+    let claim = queue.claim(30).unwrap();
+    assert_eq!(claim.value(), "first");
+    claim.ack().unwrap();
+
+    assert_eq!(queue.len().unwrap(), 1);
+    assert_eq!(queue.pop().unwrap(), "third");
+  }
+
+  #[test]
+  fn pop_watch_timeout_test() {
+    let mock = MockTransport::new();
+    let consumer_client = timeout_watch_client(&mock, 2);
+
+    let (sender, popped) = channel();
+    let consumer = thread::spawn(move || {
+      sender.send(Queue::new(&consumer_client, "queues/timeout").pop().ok()).unwrap();
+    });
+
+    // the watches on the empty queue which timed out are reissued
+    thread::sleep(Duration::from_millis(200));
+    assert!(popped.try_recv().is_err());
+
+    Queue::new(&mock.client(), "queues/timeout").push("first").unwrap();
+    assert_eq!(popped.recv().unwrap(), Some("first".to_string()));
+    consumer.join().unwrap();
+  }
+}
@@ -0,0 +1,221 @@
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use time;
+use time::Timespec;
+
+/// the members api path
+static MEMBERS_PATH: &'static str = "/v2/members";
+
+/// the id etcd gives the member of a single member cluster
+static MEMBER_ID: &'static str = "ce2a822cea30bfca";
+
+/// the name etcd gives the member of a single member cluster
+static MEMBER_NAME: &'static str = "default";
+
+/// the version of etcd the mock implements, the first with refresh
+static VERSION: &'static str = "2.3.0";
+
+struct MockMember {
+    id: String,
+    name: String,
+    peer_urls: Vec<String>,
+    client_urls: Vec<String>,
+}
+
+/// MockCluster implements the cluster apis of etcd, i.e. /v2/members and /health. The cluster starts with a single
+///  member which is always healthy and the leader, the members added never start.
+pub struct MockCluster {
+    members: Vec<MockMember>,
+    /// the id of the last member added
+    last_id: u64,
+    /// when the member started, and became the leader
+    start_time: Timespec,
+}
+
+impl MockCluster {
+    pub fn new() -> MockCluster {
+        let member = MockMember{ id: MEMBER_ID.to_string(), name: MEMBER_NAME.to_string(),
+                                 peer_urls: vec!["http://localhost:2380".to_string()], client_urls: vec![] };
+
+        return MockCluster{ members: vec![member], last_id: 0, start_time: time::get_time() };
+    }
+
+    /// the url the member is reached on, it's only known once the mock is listening
+    pub fn set_client_url(&mut self, client_url: &str) {
+        self.members[0].client_urls = vec![client_url.to_string()];
+    }
+
+    /// handles the request, None if the path isn't one of the cluster apis. body is the json body, if any
+    pub fn handle(&mut self, method: &Method, path: &str, body: &str) -> Option<(StatusCode, String)> {
+        let path = path.trim_right_matches('/');
+
+        if path.starts_with(MEMBERS_PATH) {
+            let id = path[MEMBERS_PATH.len()..].trim_left_matches('/');
+
+            return Some(match (method, id) {
+                (&Method::Get, "") => (StatusCode::Ok, self.members_json().to_string()),
+                (&Method::Post, "") => self.add(body),
+                (&Method::Delete, id) if id != "" => self.remove(id),
+                (&Method::Put, id) if id != "" => self.update(id, body),
+                _ => (StatusCode::MethodNotAllowed, String::new()),
+            });
+        }
+
+        return match (method, path) {
+            (&Method::Get, "/health") => Some((StatusCode::Ok, "{\"health\": \"true\"}".to_string())),
+            (&Method::Get, "/version") => Some((StatusCode::Ok, self.version_json().to_string())),
+            (&Method::Get, "/v2/stats/self") => Some((StatusCode::Ok, self.self_stats_json().to_string())),
+            (&Method::Get, "/v2/stats/leader") => Some((StatusCode::Ok, self.leader_stats_json().to_string())),
+            (_, "/health") | (_, "/version") | (_, "/v2/stats/self") | (_, "/v2/stats/leader") => {
+                Some((StatusCode::MethodNotAllowed, String::new()))
+            },
+            _ => None,
+        };
+    }
+
+    /// adds a member with the peer urls, it never starts so it has no name or client urls
+    fn add(&mut self, body: &str) -> (StatusCode, String) {
+        let peer_urls = match self.peer_urls(body, None) {
+            Ok(peer_urls) => peer_urls,
+            Err(error) => return error,
+        };
+
+        self.last_id += 1;
+        self.members.push(MockMember{ id: format!("{:x}", self.last_id), name: String::new(), peer_urls: peer_urls, client_urls: vec![] });
+
+        return (StatusCode::Created, member_json(self.members.last().unwrap()).to_string());
+    }
+
+    fn remove(&mut self, id: &str) -> (StatusCode, String) {
+        return match self.members.iter().position(|m| m.id == id) {
+            Some(position) => {
+                self.members.remove(position);
+                (StatusCode::NoContent, String::new())
+            },
+            None => error(StatusCode::NotFound, "etcdserver: member not found"),
+        };
+    }
+
+    fn update(&mut self, id: &str, body: &str) -> (StatusCode, String) {
+        let position = match self.members.iter().position(|m| m.id == id) {
+            Some(position) => position,
+            None => return error(StatusCode::NotFound, "etcdserver: member not found"),
+        };
+
+        return match self.peer_urls(body, Some(id)) {
+            Ok(peer_urls) => {
+                self.members[position].peer_urls = peer_urls;
+                (StatusCode::NoContent, String::new())
+            },
+            Err(error) => error,
+        };
+    }
+
+    /// decodes the {"peerURLs":[..]} of the body, none may be in use by another member than the one with the id
+    fn peer_urls(&self, body: &str, id: Option<&str>) -> Result<Vec<String>, (StatusCode, String)> {
+        let json = try!(Json::from_str(body).map_err(|_| error(StatusCode::BadRequest, "Invalid JSON in request body.")));
+        let peer_urls: Vec<String> = match json.find("peerURLs").and_then(|j| j.as_array()) {
+            Some(urls) => urls.iter().filter_map(|url| url.as_string()).map(|url| url.to_string()).collect(),
+            None => return Err(error(StatusCode::BadRequest, "peerURLs is required")),
+        };
+
+        let in_use = self.members.iter()
+                                 .filter(|m| Some(&m.id as &str) != id)
+                                 .any(|m| m.peer_urls.iter().any(|url| peer_urls.contains(url)));
+        if in_use {
+            return Err(error(StatusCode::Conflict, "etcdserver: peerURL exists"));
+        }
+
+        return Ok(peer_urls);
+    }
+
+    /// {"members":[{"id":..,"name":..,"peerURLs":[..],"clientURLs":[..]}]}
+    fn members_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert("members".to_string(), Json::Array(self.members.iter().map(member_json).collect()));
+
+        return Json::Object(obj);
+    }
+
+    /// {"etcdserver":..,"etcdcluster":..}
+    fn version_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert("etcdserver".to_string(), Json::String(VERSION.to_string()));
+        obj.insert("etcdcluster".to_string(), Json::String(VERSION.to_string()));
+
+        return Json::Object(obj);
+    }
+
+    /// the stats of the member, which is the leader
+    fn self_stats_json(&self) -> Json {
+        let member = &self.members[0];
+        let start_time = Json::String(format!("{}", time::at_utc(self.start_time).rfc3339()));
+
+        let mut leader_info = BTreeMap::new();
+        leader_info.insert("leader".to_string(), Json::String(member.id.clone()));
+        leader_info.insert("uptime".to_string(), Json::String(format!("{}s", (time::get_time() - self.start_time).num_seconds())));
+        leader_info.insert("startTime".to_string(), start_time.clone());
+
+        let mut obj = BTreeMap::new();
+        obj.insert("id".to_string(), Json::String(member.id.clone()));
+        obj.insert("name".to_string(), Json::String(member.name.clone()));
+        obj.insert("state".to_string(), Json::String("StateLeader".to_string()));
+        obj.insert("startTime".to_string(), start_time);
+        obj.insert("leaderInfo".to_string(), Json::Object(leader_info));
+        obj.insert("recvAppendRequestCnt".to_string(), Json::U64(0));
+        obj.insert("sendAppendRequestCnt".to_string(), Json::U64(0));
+
+        return Json::Object(obj);
+    }
+
+    /// the stats of the replication to the followers, the members added never start so nothing is replicated
+    fn leader_stats_json(&self) -> Json {
+        let followers: BTreeMap<String, Json> = self.members[1..].iter().map(|member| {
+            let mut latency = BTreeMap::new();
+            for field in &["current", "average", "standardDeviation", "minimum", "maximum"] {
+                latency.insert(field.to_string(), Json::F64(0.0));
+            }
+
+            let mut counts = BTreeMap::new();
+            counts.insert("fail".to_string(), Json::U64(0));
+            counts.insert("success".to_string(), Json::U64(0));
+
+            let mut obj = BTreeMap::new();
+            obj.insert("latency".to_string(), Json::Object(latency));
+            obj.insert("counts".to_string(), Json::Object(counts));
+
+            (member.id.clone(), Json::Object(obj))
+        }).collect();
+
+        let mut obj = BTreeMap::new();
+        obj.insert("leader".to_string(), Json::String(self.members[0].id.clone()));
+        obj.insert("followers".to_string(), Json::Object(followers));
+
+        return Json::Object(obj);
+    }
+}
+
+/// {"id":..,"name":..,"peerURLs":[..],"clientURLs":[..]}
+fn member_json(member: &MockMember) -> Json {
+    let mut obj = BTreeMap::new();
+    obj.insert("id".to_string(), Json::String(member.id.clone()));
+    obj.insert("name".to_string(), Json::String(member.name.clone()));
+    obj.insert("peerURLs".to_string(), urls_json(&member.peer_urls));
+    obj.insert("clientURLs".to_string(), urls_json(&member.client_urls));
+
+    return Json::Object(obj);
+}
+
+fn urls_json(urls: &[String]) -> Json {
+    return Json::Array(urls.iter().map(|url| Json::String(url.clone())).collect());
+}
+
+/// the cluster apis answer errors with a message, i.e. {"message":"etcdserver: member not found"}
+fn error(status: StatusCode, message: &str) -> (StatusCode, String) {
+    let mut obj = BTreeMap::new();
+    obj.insert("message".to_string(), Json::String(message.to_string()));
+
+    return (status, Json::Object(obj).to_string());
+}
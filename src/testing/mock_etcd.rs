@@ -0,0 +1,130 @@
+use hyper::header::ContentType;
+use hyper::mime::{Mime, TopLevel, SubLevel};
+use hyper::net::Fresh;
+use hyper::server::{Handler, Listening, Request, Response, Server};
+use hyper::uri::RequestUri;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url;
+use etcd::{EtcdClient, EtcdClientBuilder};
+use testing::mock_store::{MockStore, DEFAULT_HISTORY};
+
+/// each request, including blocked watches, holds one of these threads
+static SERVER_THREADS: usize = 32;
+
+/// a watch without an event is answered with an empty body after this long, as etcd does, so that the thread of a
+///  watch whose client went away is freed
+static WATCH_TIMEOUT_SECS: u64 = 5;
+
+/// MockEtcd is an in process etcd server implementing the v2 keys api in memory.
+///
+/// It listens on an ephemeral port on localhost until dropped.
+///
+/// ```no_run
+/// use etcd_rs::testing::MockEtcd;
+///
+/// let etcd = MockEtcd::new();
+/// let client = etcd.client();
+///
+/// client.set("foo", "bar", None).unwrap();
+/// assert_eq!(client.get("foo").unwrap().unwrap().value.unwrap(), "bar");
+/// ```
+pub struct MockEtcd {
+    listening: Listening,
+}
+
+impl MockEtcd {
+    pub fn new() -> MockEtcd {
+        return MockEtcd::with_history(DEFAULT_HISTORY);
+    }
+
+    /// history is the number of events kept for watches with a waitIndex, older indexes return EventIndexCleared
+    pub fn with_history(history: usize) -> MockEtcd {
+        let store = Arc::new(MockStore::new(history));
+        let handler = MockHandler{ store: store.clone() };
+        let listening = Server::http(handler).listen_threads("127.0.0.1:0", SERVER_THREADS).unwrap();
+        debug!("MockEtcd listening on {}", listening.socket);
+
+        let etcd = MockEtcd{ listening: listening };
+        store.set_client_url(&etcd.endpoint());
+
+        return etcd;
+    }
+
+    /// the url of the server, i.e. http://127.0.0.1:port
+    pub fn endpoint(&self) -> String {
+        return format!("http://{}", self.listening.socket);
+    }
+
+    /// a client connected to this server
+    pub fn client(&self) -> EtcdClient {
+        return EtcdClientBuilder::new().endpoint(&self.endpoint()).build().unwrap();
+    }
+}
+
+impl Drop for MockEtcd {
+    fn drop(&mut self) {
+        // the listener thread is detached, it would otherwise be joined forever
+        let _ = self.listening.close();
+    }
+}
+
+/// translates the http requests for the MockStore
+struct MockHandler {
+    store: Arc<MockStore>,
+}
+
+impl Handler for MockHandler {
+    fn handle<'a, 'k>(&'a self, mut request: Request<'a, 'k>, mut response: Response<'a, Fresh>) {
+        let mut body = String::new();
+        if let Err(e) = request.read_to_string(&mut body) {
+            warn!("could not read request: {}", e);
+            return;
+        }
+
+        let uri: String = match request.uri {
+            RequestUri::AbsolutePath(ref path) => path.clone(),
+            ref uri => {
+                warn!("unsupported uri: {:?}", uri);
+                return;
+            },
+        };
+
+        let (path, query) = match uri.find('?') {
+            Some(i) => (&uri[..i], &uri[i + 1..]),
+            None => (&uri as &str, ""),
+        };
+
+        let json = request.headers.get::<ContentType>().map_or(false, |&ContentType(ref mime)| {
+            match *mime { Mime(TopLevel::Application, SubLevel::Json, _) => true, _ => false }
+        });
+
+        let result = if json {
+            self.store.handle_json(&request.method, path, &body)
+        } else {
+            // form values take precedence over query values, as they do in etcd
+            let mut params = url::form_urlencoded::parse(body.as_bytes());
+            params.extend(url::form_urlencoded::parse(query.as_bytes()).into_iter());
+
+            // the client can't be seen to disconnect, so a blocked watch gives up at the deadline
+            let deadline = Instant::now() + Duration::from_secs(WATCH_TIMEOUT_SECS);
+            match self.store.handle_until(&request.method, path, &params, &|| Instant::now() >= deadline) {
+                Some(result) => result,
+                None => self.store.empty_response(),
+            }
+        };
+
+        *response.status_mut() = result.status;
+        *response.headers_mut() = result.headers();
+
+        let written = response.start().and_then(|mut response| {
+            try!(response.write_all(result.body.as_bytes()));
+            response.end()
+        });
+
+        if let Err(e) = written {
+            warn!("could not write response: {}", e);
+        }
+    }
+}
@@ -0,0 +1,865 @@
+use hyper::header::{ContentType, Headers};
+use hyper::method::Method;
+use hyper::mime::{Mime, TopLevel, SubLevel};
+use hyper::status::StatusCode;
+use rustc_serialize::json::Json;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use time;
+use time::Timespec;
+use url;
+use etcd::etcd_error::EtcdErrorCode;
+use etcd::header::{XEtcdIndex, XRaftIndex, XRaftTerm};
+use testing::mock_cluster::MockCluster;
+
+/// etcd keeps the last 1000 events for watches with a waitIndex
+pub static DEFAULT_HISTORY: usize = 1000;
+
+/// how often blocked watches check for expired keys
+static EXPIRE_POLL_MS: u64 = 100;
+
+/// the prefix of the keys api
+static KEYS_PATH: &'static str = "/v2/keys";
+
+/// the stats of the store, the other stats are of the cluster
+static STORE_STATS_PATH: &'static str = "/v2/stats/store";
+
+/// The response to a request, the headers are derived from the indexes.
+pub struct MockResponse {
+    pub status: StatusCode,
+    /// X-Etcd-Index, also used for X-Raft-Index
+    pub etcd_index: u64,
+    /// the json body
+    pub body: String,
+}
+
+impl MockResponse {
+    /// the headers etcd sends with the response
+    pub fn headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        headers.set(XEtcdIndex(self.etcd_index));
+        headers.set(XRaftIndex(self.etcd_index));
+        headers.set(XRaftTerm(1));
+        headers.set(ContentType(Mime(TopLevel::Application, SubLevel::Json, vec![])));
+
+        return headers;
+    }
+}
+
+#[derive(Clone)]
+struct MockNode {
+    key: String,
+    /// None for directories
+    value: Option<String>,
+    dir: bool,
+    created_index: u64,
+    modified_index: u64,
+    expiration: Option<Timespec>,
+}
+
+/// a change to the store, kept for watches
+struct Event {
+    index: u64,
+    key: String,
+    action: String,
+    /// the full response object, i.e. {"action":..,"node":..,"prevNode":..}
+    json: Json,
+}
+
+struct StoreState {
+    index: u64,
+    /// all the nodes by key, this includes the root "/"
+    nodes: BTreeMap<String, MockNode>,
+    events: VecDeque<Event>,
+    history: usize,
+    /// the index of the last event dropped from the history
+    cleared: u64,
+    /// the counts of /v2/stats/store by name, i.e. getsSuccess
+    stats: BTreeMap<String, u64>,
+}
+
+/// MockStore implements the semantics of the etcd v2 keys api in memory.
+pub struct MockStore {
+    state: Mutex<StoreState>,
+    changed: Condvar,
+    /// answers the requests which aren't for the keys api
+    cluster: Mutex<MockCluster>,
+}
+
+impl MockStore {
+    /// history is the number of events kept for watches with a waitIndex, older indexes return EventIndexCleared
+    pub fn new(history: usize) -> MockStore {
+        let mut nodes = BTreeMap::new();
+        nodes.insert("/".to_string(), MockNode{ key: "/".to_string(), value: None, dir: true,
+                                                created_index: 0, modified_index: 0, expiration: None });
+
+        return MockStore {
+            state: Mutex::new(StoreState{ index: 0, nodes: nodes, events: VecDeque::new(), history: history, cleared: 0,
+                                          stats: BTreeMap::new() }),
+            changed: Condvar::new(),
+            cluster: Mutex::new(MockCluster::new()),
+        };
+    }
+
+    /// the url the member of the mock cluster is reached on, as listed by /v2/members
+    pub fn set_client_url(&self, client_url: &str) {
+        self.cluster.lock().unwrap().set_client_url(client_url);
+    }
+
+    /// handles the request, path is the path of the url, i.e. /v2/keys/foo, the params are the decoded query and form
+    pub fn handle(&self, method: &Method, path: &str, params: &[(String, String)]) -> MockResponse {
+        return self.handle_until(method, path, params, &|| false).unwrap();
+    }
+
+    /// handles the request as handle() does, a blocked watch gives up and returns None once cancelled returns true
+    pub fn handle_until(&self, method: &Method, path: &str, params: &[(String, String)], cancelled: &Fn() -> bool) -> Option<MockResponse> {
+        if !path.starts_with(KEYS_PATH) {
+            return Some(self.handle_json(method, path, ""));
+        }
+
+        let key = normalize_key(&path[KEYS_PATH.len()..]);
+
+        if *method == Method::Get && bool_param(params, "wait") {
+            return self.watch(&key, params, cancelled);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.expire(time::get_time());
+
+        let result = match *method {
+            Method::Get => state.get(&key, params),
+            Method::Put => state.set(&key, params),
+            Method::Post => state.create_in_order(&key, params),
+            Method::Delete => state.delete(&key, params),
+            _ => return Some(MockResponse{ status: StatusCode::MethodNotAllowed, etcd_index: state.index, body: String::new() }),
+        };
+
+        let outcome = if result.is_ok() { "Success" } else { "Fail" };
+        state.count(&format!("{}{}", operation(method, params), outcome));
+
+        // wake any watches, they check for themselves whether the change is relevant
+        self.changed.notify_all();
+
+        return Some(match result {
+            Ok((status, body)) => MockResponse{ status: status, etcd_index: state.index, body: body.to_string() },
+            Err(error) => error,
+        });
+    }
+
+    /// the response etcd gives to a watch which timed out, an empty body at the current index
+    pub fn empty_response(&self) -> MockResponse {
+        return MockResponse{ status: StatusCode::Ok, etcd_index: self.state.lock().unwrap().index, body: String::new() };
+    }
+
+    /// handles a request to the cluster apis, which take json rather than forms, body is the json body if any
+    pub fn handle_json(&self, method: &Method, path: &str, body: &str) -> MockResponse {
+        let state = self.state.lock().unwrap();
+        let index = state.index;
+
+        // the store counts its own stats
+        if *method == Method::Get && path.trim_right_matches('/') == STORE_STATS_PATH {
+            return MockResponse{ status: StatusCode::Ok, etcd_index: index, body: state.stats_json().to_string() };
+        }
+
+        return match self.cluster.lock().unwrap().handle(method, path, body) {
+            Some((status, body)) => MockResponse{ status: status, etcd_index: index, body: body },
+            None => MockResponse{ status: StatusCode::NotFound, etcd_index: index, body: "404 page not found".to_string() },
+        };
+    }
+
+    /// blocks until there is an event for the key at or after the waitIndex, or the watch is cancelled
+    fn watch(&self, key: &str, params: &[(String, String)], cancelled: &Fn() -> bool) -> Option<MockResponse> {
+        let recursive = bool_param(params, "recursive");
+        let mut state = self.state.lock().unwrap();
+        state.expire(time::get_time());
+
+        let wait_index = match param(params, "waitIndex").map(|i| i.parse::<u64>()) {
+            Some(Ok(i)) => i,
+            Some(Err(_)) => return Some(state.error(EtcdErrorCode::IndexNaN, "waitIndex")),
+            None => state.index + 1,
+        };
+
+        if wait_index <= state.cleared {
+            let cause = format!("the requested history has been cleared [{}/{}]", state.cleared + 1, wait_index);
+            return Some(state.error(EtcdErrorCode::EventIndexCleared, &cause));
+        }
+
+        state.count("watchers");
+        loop {
+            let event = state.events.iter().find(|e| e.index >= wait_index && e.matches(key, recursive)).map(|e| e.json.to_string());
+            if let Some(body) = event {
+                state.uncount("watchers");
+                return Some(MockResponse{ status: StatusCode::Ok, etcd_index: state.index, body: body });
+            }
+
+            if cancelled() {
+                state.uncount("watchers");
+                return None;
+            }
+
+            state = self.changed.wait_timeout(state, Duration::from_millis(EXPIRE_POLL_MS)).unwrap().0;
+            if state.expire(time::get_time()) {
+                self.changed.notify_all();
+            }
+        }
+    }
+}
+
+impl Event {
+    /// true if the event is for the key, a key beneath it if recursive, or the removal of a parent directory
+    fn matches(&self, key: &str, recursive: bool) -> bool {
+        if self.key == key {
+            return true;
+        }
+
+        // a recursive watch isn't told of the changes to hidden keys beneath it
+        if recursive && self.key.starts_with(&child_prefix(key)) && !is_hidden(key, &self.key) {
+            return true;
+        }
+
+        let removed = self.action == "delete" || self.action == "expire" || self.action == "compareAndDelete";
+        return removed && key.starts_with(&child_prefix(&self.key));
+    }
+}
+
+type StoreResult = Result<(StatusCode, Json), MockResponse>;
+
+impl StoreState {
+    fn error(&self, code: EtcdErrorCode, cause: &str) -> MockResponse {
+        let status = match code {
+            EtcdErrorCode::KeyNotFound => StatusCode::NotFound,
+            EtcdErrorCode::TestFailed | EtcdErrorCode::NodeExist => StatusCode::PreconditionFailed,
+            EtcdErrorCode::NotFile | EtcdErrorCode::DirNotEmpty => StatusCode::Forbidden,
+            _ => StatusCode::BadRequest,
+        };
+
+        let mut obj = BTreeMap::new();
+        obj.insert("errorCode".to_string(), Json::U64(code.code()));
+        obj.insert("message".to_string(), Json::String(error_message(code).to_string()));
+        obj.insert("cause".to_string(), Json::String(cause.to_string()));
+        obj.insert("index".to_string(), Json::U64(self.index));
+
+        return MockResponse{ status: status, etcd_index: self.index, body: Json::Object(obj).to_string() };
+    }
+
+    fn count(&mut self, name: &str) {
+        *self.stats.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    fn uncount(&mut self, name: &str) {
+        *self.stats.entry(name.to_string()).or_insert(1) -= 1;
+    }
+
+    /// {"getsSuccess":..,"getsFail":..,..}
+    fn stats_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        for operation in &["gets", "sets", "delete", "update", "create", "compareAndSwap", "compareAndDelete"] {
+            for outcome in &["Success", "Fail"] {
+                let name = format!("{}{}", operation, outcome);
+                obj.insert(name.clone(), Json::U64(*self.stats.get(&name).unwrap_or(&0)));
+            }
+        }
+
+        for name in &["expireCount", "watchers"] {
+            obj.insert(name.to_string(), Json::U64(*self.stats.get(*name).unwrap_or(&0)));
+        }
+
+        return Json::Object(obj);
+    }
+
+    /// records the change for watches, dropping the oldest events beyond the history
+    fn record(&mut self, key: &str, action: &str, json: &Json) {
+        self.events.push_back(Event{ index: self.index, key: key.to_string(), action: action.to_string(), json: json.clone() });
+
+        while self.events.len() > self.history {
+            if let Some(event) = self.events.pop_front() {
+                self.cleared = event.index;
+            }
+        }
+    }
+
+    /// removes all the expired nodes, returns true if any were removed
+    fn expire(&mut self, now: Timespec) -> bool {
+        let expired: Vec<String> = self.nodes.values()
+                                             .filter(|n| n.expiration.map_or(false, |e| e <= now))
+                                             .map(|n| n.key.clone())
+                                             .collect();
+
+        let mut changed = false;
+        for key in expired {
+            // may have been removed along with an expired parent
+            let node = match self.nodes.get(&key) {
+                Some(node) => node.clone(),
+                None => continue,
+            };
+
+            self.index += 1;
+            self.remove(&key);
+            self.count("expireCount");
+
+            let json = self.response("expire", &self.removed_json(&node), Some(&node), now);
+            let index = self.index;
+            self.record(&key, "expire", &json);
+            debug!("expired {} at {}", key, index);
+            changed = true;
+        }
+
+        return changed;
+    }
+
+    fn get(&mut self, key: &str, params: &[(String, String)]) -> StoreResult {
+        let node = match self.nodes.get(key) {
+            Some(node) => node.clone(),
+            None => return Err(self.error(EtcdErrorCode::KeyNotFound, key)),
+        };
+
+        let node_json = self.list_json(&node, bool_param(params, "recursive"), time::get_time());
+        return Ok((StatusCode::Ok, self.response("get", &node_json, None, time::get_time())));
+    }
+
+    fn set(&mut self, key: &str, params: &[(String, String)]) -> StoreResult {
+        let now = time::get_time();
+        if key == "/" {
+            return Err(self.error(EtcdErrorCode::RootROnly, key));
+        }
+
+        let dir = bool_param(params, "dir");
+        let value = param(params, "value");
+        let ttl = try!(self.ttl_param(params));
+        let prev_exist = param(params, "prevExist").map(|b| b == "true");
+        let prev_value = param(params, "prevValue");
+        let prev_index = match param(params, "prevIndex").map(|i| i.parse::<u64>()) {
+            Some(Ok(i)) => Some(i),
+            Some(Err(_)) => return Err(self.error(EtcdErrorCode::IndexNaN, "prevIndex")),
+            None => None,
+        };
+
+        let existing: Option<MockNode> = self.nodes.get(key).cloned();
+
+        if prev_value.is_some() || prev_index.is_some() {
+            try!(self.compare(key, existing.as_ref(), prev_value, prev_index));
+        }
+
+        if bool_param(params, "refresh") {
+            return self.refresh(key, existing, value, ttl, now);
+        }
+
+        let action = if prev_value.is_some() || prev_index.is_some() {
+            "compareAndSwap"
+        } else {
+            match (prev_exist, &existing) {
+                (Some(false), &Some(_)) => return Err(self.error(EtcdErrorCode::NodeExist, key)),
+                (Some(false), &None) => "create",
+                (Some(true), &None) => return Err(self.error(EtcdErrorCode::KeyNotFound, key)),
+                (Some(true), &Some(ref node)) if node.dir && !dir => return Err(self.error(EtcdErrorCode::NotFile, key)),
+                (Some(true), &Some(_)) => "update",
+                (None, &Some(ref node)) if node.dir => return Err(self.error(EtcdErrorCode::NotFile, key)),
+                (None, _) => "set",
+            }
+        };
+
+        try!(self.make_parents(key));
+        self.index += 1;
+
+        // updates keep the created index, the others replace the node
+        let created_index = match (action, &existing) {
+            ("update", &Some(ref node)) | ("compareAndSwap", &Some(ref node)) => node.created_index,
+            _ => self.index,
+        };
+
+        let node = MockNode {
+            key: key.to_string(),
+            value: if dir { None } else { Some(value.unwrap_or("").to_string()) },
+            dir: dir,
+            created_index: created_index,
+            modified_index: self.index,
+            expiration: ttl.map(|ttl| now + time::Duration::seconds(ttl as i64)),
+        };
+
+        self.nodes.insert(key.to_string(), node.clone());
+
+        let json = self.response(action, &self.node_json(&node, now), existing.as_ref(), now);
+        self.record(key, action, &json);
+
+        let status = if existing.is_some() { StatusCode::Ok } else { StatusCode::Created };
+        return Ok((status, json));
+    }
+
+    /// checks the conditions of a compareAndSwap against the existing node
+    fn compare(&self, key: &str, existing: Option<&MockNode>, prev_value: Option<&str>, prev_index: Option<u64>) -> Result<(), MockResponse> {
+        let existing = match existing {
+            Some(node) => node,
+            None => return Err(self.error(EtcdErrorCode::KeyNotFound, key)),
+        };
+
+        if existing.dir {
+            return Err(self.error(EtcdErrorCode::NotFile, key));
+        }
+
+        if let Some(prev_value) = prev_value {
+            if existing.value.as_ref().map(|v| v as &str) != Some(prev_value) {
+                let cause = format!("[{} != {}]", prev_value, existing.value.as_ref().map(|v| v as &str).unwrap_or(""));
+                return Err(self.error(EtcdErrorCode::TestFailed, &cause));
+            }
+        }
+
+        if let Some(prev_index) = prev_index {
+            if existing.modified_index != prev_index {
+                let cause = format!("[{} != {}]", prev_index, existing.modified_index);
+                return Err(self.error(EtcdErrorCode::TestFailed, &cause));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// resets the ttl of the node without notifying watches
+    fn refresh(&mut self, key: &str, existing: Option<MockNode>, value: Option<&str>, ttl: Option<u64>, now: Timespec) -> StoreResult {
+        if value.is_some() {
+            return Err(self.error(EtcdErrorCode::RefreshValue, key));
+        }
+
+        let ttl = match ttl {
+            Some(ttl) => ttl,
+            None => return Err(self.error(EtcdErrorCode::RefreshTTLRequired, key)),
+        };
+
+        let existing = match existing {
+            Some(node) => node,
+            None => return Err(self.error(EtcdErrorCode::KeyNotFound, key)),
+        };
+
+        self.index += 1;
+
+        let mut node = existing.clone();
+        node.modified_index = self.index;
+        node.expiration = Some(now + time::Duration::seconds(ttl as i64));
+        self.nodes.insert(key.to_string(), node.clone());
+
+        return Ok((StatusCode::Ok, self.response("update", &self.node_json(&node, now), Some(&existing), now)));
+    }
+
+    fn create_in_order(&mut self, dir: &str, params: &[(String, String)]) -> StoreResult {
+        let now = time::get_time();
+        let ttl = try!(self.ttl_param(params));
+
+        match self.nodes.get(dir) {
+            Some(node) if !node.dir => return Err(self.error(EtcdErrorCode::NotDir, dir)),
+            _ => (),
+        }
+
+        let key = format!("{}{:020}", child_prefix(dir), self.index + 1);
+        try!(self.make_parents(&key));
+        self.index += 1;
+
+        let node = MockNode {
+            key: key.clone(),
+            value: Some(param(params, "value").unwrap_or("").to_string()),
+            dir: false,
+            created_index: self.index,
+            modified_index: self.index,
+            expiration: ttl.map(|ttl| now + time::Duration::seconds(ttl as i64)),
+        };
+
+        self.nodes.insert(key.clone(), node.clone());
+
+        let json = self.response("create", &self.node_json(&node, now), None, now);
+        self.record(&key, "create", &json);
+
+        return Ok((StatusCode::Created, json));
+    }
+
+    fn delete(&mut self, key: &str, params: &[(String, String)]) -> StoreResult {
+        let now = time::get_time();
+        if key == "/" {
+            return Err(self.error(EtcdErrorCode::RootROnly, key));
+        }
+
+        let existing = match self.nodes.get(key) {
+            Some(node) => node.clone(),
+            None => return Err(self.error(EtcdErrorCode::KeyNotFound, key)),
+        };
+
+        let recursive = bool_param(params, "recursive");
+        if existing.dir {
+            if !bool_param(params, "dir") && !recursive {
+                return Err(self.error(EtcdErrorCode::NotFile, key));
+            }
+
+            if !recursive && self.children(key).len() > 0 {
+                return Err(self.error(EtcdErrorCode::DirNotEmpty, key));
+            }
+        }
+
+        let prev_value = param(params, "prevValue");
+        let prev_index = param(params, "prevIndex");
+        let action = if prev_value.is_some() || prev_index.is_some() {
+            if existing.dir {
+                return Err(self.error(EtcdErrorCode::NotFile, key));
+            }
+
+            if let Some(prev_value) = prev_value {
+                if existing.value.as_ref().map(|v| v as &str) != Some(prev_value) {
+                    let cause = format!("[{} != {}]", prev_value, existing.value.as_ref().map(|v| v as &str).unwrap_or(""));
+                    return Err(self.error(EtcdErrorCode::TestFailed, &cause));
+                }
+            }
+
+            if let Some(prev_index) = prev_index {
+                if existing.modified_index.to_string() != prev_index {
+                    let cause = format!("[{} != {}]", prev_index, existing.modified_index);
+                    return Err(self.error(EtcdErrorCode::TestFailed, &cause));
+                }
+            }
+
+            "compareAndDelete"
+        } else {
+            "delete"
+        };
+
+        self.index += 1;
+        self.remove(key);
+
+        let json = self.response(action, &self.removed_json(&existing), Some(&existing), now);
+        self.record(key, action, &json);
+
+        return Ok((StatusCode::Ok, json));
+    }
+
+    /// the ttl param, an empty ttl is the same as no ttl
+    fn ttl_param(&self, params: &[(String, String)]) -> Result<Option<u64>, MockResponse> {
+        return match param(params, "ttl") {
+            Some("") | None => Ok(None),
+            Some(ttl) => match ttl.parse::<u64>() {
+                Ok(ttl) => Ok(Some(ttl)),
+                Err(_) => Err(self.error(EtcdErrorCode::TTLNaN, "ttl")),
+            },
+        };
+    }
+
+    /// creates the missing parent directories of the key at the next index
+    fn make_parents(&mut self, key: &str) -> Result<(), MockResponse> {
+        let index = self.index + 1;
+        let mut parent = String::new();
+        let components: Vec<&str> = key.split('/').filter(|c| !c.is_empty()).collect();
+
+        for component in &components[..components.len().saturating_sub(1)] {
+            parent.push('/');
+            parent.push_str(component);
+
+            match self.nodes.get(&parent) {
+                Some(node) if node.dir => continue,
+                Some(_) => return Err(self.error(EtcdErrorCode::NotDir, &parent)),
+                None => (),
+            }
+
+            self.nodes.insert(parent.clone(), MockNode{ key: parent.clone(), value: None, dir: true,
+                                                        created_index: index, modified_index: index, expiration: None });
+        }
+
+        return Ok(());
+    }
+
+    /// removes the node and everything beneath it
+    fn remove(&mut self, key: &str) {
+        let prefix = child_prefix(key);
+        let descendants: Vec<String> = self.nodes.keys().filter(|k| k.starts_with(&prefix)).cloned().collect();
+
+        for descendant in descendants {
+            self.nodes.remove(&descendant);
+        }
+
+        self.nodes.remove(key);
+    }
+
+    /// the direct children of the directory, sorted by key
+    fn children(&self, key: &str) -> Vec<MockNode> {
+        let prefix = child_prefix(key);
+        return self.nodes.values()
+                         .filter(|n| n.key.starts_with(&prefix) && !n.key[prefix.len()..].contains('/') && n.key != "/")
+                         .cloned()
+                         .collect();
+    }
+
+    /// {"action":..,"node":..,"prevNode":..}
+    fn response(&self, action: &str, node: &Json, prev_node: Option<&MockNode>, now: Timespec) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert("action".to_string(), Json::String(action.to_string()));
+        obj.insert("node".to_string(), node.clone());
+
+        if let Some(prev_node) = prev_node {
+            obj.insert("prevNode".to_string(), self.node_json(prev_node, now));
+        }
+
+        return Json::Object(obj);
+    }
+
+    fn node_json(&self, node: &MockNode, now: Timespec) -> Json {
+        let mut obj = BTreeMap::new();
+
+        // etcd renders the root as {"dir":true,"nodes":[..]}, without a key or indexes
+        if node.key != "/" {
+            obj.insert("key".to_string(), Json::String(node.key.clone()));
+            obj.insert("createdIndex".to_string(), Json::U64(node.created_index));
+            obj.insert("modifiedIndex".to_string(), Json::U64(node.modified_index));
+        }
+
+        if node.dir {
+            obj.insert("dir".to_string(), Json::Boolean(true));
+        }
+
+        if let Some(ref value) = node.value {
+            obj.insert("value".to_string(), Json::String(value.clone()));
+        }
+
+        if let Some(expiration) = node.expiration {
+            // etcd rounds the remaining ttl up to the second
+            let remaining = (expiration - now).num_milliseconds();
+            obj.insert("ttl".to_string(), Json::I64((remaining + 999) / 1000));
+            obj.insert("expiration".to_string(), Json::String(format!("{}", time::at_utc(expiration).rfc3339())));
+        }
+
+        return Json::Object(obj);
+    }
+
+    /// the node of a delete or expire, the value is removed and it is modified at the current index
+    fn removed_json(&self, node: &MockNode) -> Json {
+        let mut removed = node.clone();
+        removed.value = None;
+        removed.expiration = None;
+        removed.modified_index = self.index;
+
+        return self.node_json(&removed, time::get_time());
+    }
+
+    /// the node along with its children, and their children if recursive
+    fn list_json(&self, node: &MockNode, recursive: bool, now: Timespec) -> Json {
+        let mut json = self.node_json(node, now);
+        if !node.dir {
+            return json;
+        }
+
+        // hidden keys are only read by their own key, never in a listing
+        let children: Vec<Json> = self.children(&node.key)
+                                      .iter()
+                                      .filter(|child| !is_hidden(&node.key, &child.key))
+                                      .map(|child| if recursive { self.list_json(child, true, now) } else { self.node_json(child, now) })
+                                      .collect();
+
+        if !children.is_empty() {
+            if let Json::Object(ref mut obj) = json {
+                obj.insert("nodes".to_string(), Json::Array(children));
+            }
+        }
+
+        return json;
+    }
+}
+
+/// the prefix of all the keys beneath the key
+fn child_prefix(key: &str) -> String {
+    return if key == "/" { "/".to_string() } else { format!("{}/", key) };
+}
+
+/// true if a component of the key beneath the parent starts with _, etcd hides such keys from listings and
+///  recursive watches of the parent
+fn is_hidden(parent: &str, key: &str) -> bool {
+    return key[child_prefix(parent).len()..].split('/').any(|c| c.starts_with('_'));
+}
+
+/// the decoded key with a leading slash and without empty components, i.e. //foo/bar/ is /foo/bar
+fn normalize_key(path: &str) -> String {
+    let decoded = url::percent_encoding::lossy_utf8_percent_decode(path.as_bytes());
+    let components: Vec<&str> = decoded.split('/').filter(|c| !c.is_empty()).collect();
+
+    return format!("/{}", components.join("/"));
+}
+
+/// the name etcd counts the operation as in the store stats, i.e. a PUT with a prevValue is a compareAndSwap
+fn operation(method: &Method, params: &[(String, String)]) -> &'static str {
+    let compare = param(params, "prevValue").is_some() || param(params, "prevIndex").is_some();
+
+    return match *method {
+        Method::Get => "gets",
+        Method::Post => "create",
+        Method::Delete if compare => "compareAndDelete",
+        Method::Delete => "delete",
+        _ if compare => "compareAndSwap",
+        _ => match param(params, "prevExist") {
+            Some("false") => "create",
+            Some("true") => "update",
+            _ => "sets",
+        },
+    };
+}
+
+/// the first value of the param, form values are before query values
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    return params.iter().find(|&&(ref k, _)| k == name).map(|&(_, ref v)| v as &str);
+}
+
+fn bool_param(params: &[(String, String)], name: &str) -> bool {
+    return param(params, name) == Some("true");
+}
+
+fn error_message(code: EtcdErrorCode) -> &'static str {
+    return match code {
+        EtcdErrorCode::KeyNotFound => "Key not found",
+        EtcdErrorCode::TestFailed => "Compare failed",
+        EtcdErrorCode::NotFile => "Not a file",
+        EtcdErrorCode::NotDir => "Not a directory",
+        EtcdErrorCode::NodeExist => "Key already exists",
+        EtcdErrorCode::RootROnly => "Root is read only",
+        EtcdErrorCode::DirNotEmpty => "Directory not empty",
+        EtcdErrorCode::TTLNaN => "The given TTL in POST form is not a number",
+        EtcdErrorCode::IndexNaN => "The given index in POST form is not a number",
+        EtcdErrorCode::RefreshValue => "Value provided on refresh",
+        EtcdErrorCode::RefreshTTLRequired => "A TTL must be provided on refresh",
+        EtcdErrorCode::EventIndexCleared => "The event in requested index is outdated and cleared",
+        _ => "Unknown error",
+    };
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::method::Method;
+  use hyper::status::StatusCode;
+  use rustc_serialize::json::Json;
+  use super::{MockStore, DEFAULT_HISTORY};
+
+  fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    return pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+  }
+
+  fn body_of(store: &MockStore, method: Method, path: &str, pairs: &[(&str, &str)]) -> (StatusCode, Json) {
+    let response = store.handle(&method, path, &params(pairs));
+    return (response.status, Json::from_str(&response.body).unwrap());
+  }
+
+  #[test]
+  fn set_get_test() {
+    let store = MockStore::new(DEFAULT_HISTORY);
+
+    let (status, json) = body_of(&store, Method::Put, "/v2/keys/dir/key", &[("value", "one")]);
+    assert_eq!(status, StatusCode::Created);
+    assert_eq!(json.find_path(&["node", "modifiedIndex"]).unwrap().as_u64(), Some(1));
+
+    let (status, json) = body_of(&store, Method::Get, "/v2/keys/dir", &[]);
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(json.find_path(&["node", "dir"]).unwrap().as_boolean(), Some(true));
+
+    let nodes = json.find_path(&["node", "nodes"]).unwrap().as_array().unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].find("value").unwrap().as_string(), Some("one"));
+  }
+
+  #[test]
+  fn errors_test() {
+    let store = MockStore::new(DEFAULT_HISTORY);
+
+    let (status, json) = body_of(&store, Method::Get, "/v2/keys/missing", &[]);
+    assert_eq!(status, StatusCode::NotFound);
+    assert_eq!(json.find("errorCode").unwrap().as_u64(), Some(100));
+
+    body_of(&store, Method::Put, "/v2/keys/key", &[("value", "one")]);
+
+    let (status, json) = body_of(&store, Method::Put, "/v2/keys/key", &[("value", "two"), ("prevValue", "wrong")]);
+    assert_eq!(status, StatusCode::PreconditionFailed);
+    assert_eq!(json.find("errorCode").unwrap().as_u64(), Some(101));
+
+    let (_, json) = body_of(&store, Method::Put, "/v2/keys/key", &[("value", "two"), ("prevExist", "false")]);
+    assert_eq!(json.find("errorCode").unwrap().as_u64(), Some(105));
+
+    let (_, json) = body_of(&store, Method::Put, "/v2/keys/key/child", &[("value", "two")]);
+    assert_eq!(json.find("errorCode").unwrap().as_u64(), Some(104));
+  }
+
+  #[test]
+  fn hidden_test() {
+    let store = MockStore::new(DEFAULT_HISTORY);
+
+    body_of(&store, Method::Put, "/v2/keys/dir/_hidden", &[("value", "one")]);
+    body_of(&store, Method::Put, "/v2/keys/dir/visible", &[("value", "two")]);
+
+    // hidden keys are only read by their own key
+    let (_, json) = body_of(&store, Method::Get, "/v2/keys/dir", &[("recursive", "true")]);
+    let nodes = json.find_path(&["node", "nodes"]).unwrap().as_array().unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].find("key").unwrap().as_string(), Some("/dir/visible"));
+
+    let (_, json) = body_of(&store, Method::Get, "/v2/keys/dir/_hidden", &[]);
+    assert_eq!(json.find_path(&["node", "value"]).unwrap().as_string(), Some("one"));
+
+    // nor does a recursive watch of the directory see their changes
+    let (_, json) = body_of(&store, Method::Get, "/v2/keys/dir", &[("wait", "true"), ("waitIndex", "1"), ("recursive", "true")]);
+    assert_eq!(json.find_path(&["node", "key"]).unwrap().as_string(), Some("/dir/visible"));
+  }
+
+  #[test]
+  fn root_test() {
+    let store = MockStore::new(DEFAULT_HISTORY);
+
+    body_of(&store, Method::Put, "/v2/keys/foo", &[("value", "bar")]);
+
+    // the root has no key or indexes, as with etcd
+    let (_, json) = body_of(&store, Method::Get, "/v2/keys/", &[]);
+    let root = json.find("node").unwrap().as_object().unwrap();
+    assert_eq!(root.get("dir").unwrap().as_boolean(), Some(true));
+    assert!(root.get("key").is_none());
+    assert!(root.get("createdIndex").is_none());
+    assert!(root.get("modifiedIndex").is_none());
+
+    let nodes = root.get("nodes").unwrap().as_array().unwrap();
+    assert_eq!(nodes[0].find("key").unwrap().as_string(), Some("/foo"));
+  }
+
+  #[test]
+  fn in_order_test() {
+    let store = MockStore::new(DEFAULT_HISTORY);
+
+    body_of(&store, Method::Post, "/v2/keys/queue", &[("value", "first")]);
+    body_of(&store, Method::Post, "/v2/keys/queue", &[("value", "second")]);
+
+    let (_, json) = body_of(&store, Method::Get, "/v2/keys/queue", &[("sorted", "true")]);
+    let nodes = json.find_path(&["node", "nodes"]).unwrap().as_array().unwrap();
+    assert_eq!(nodes[0].find("value").unwrap().as_string(), Some("first"));
+    assert_eq!(nodes[1].find("value").unwrap().as_string(), Some("second"));
+  }
+
+  #[test]
+  fn watch_history_test() {
+    let store = MockStore::new(2);
+
+    for value in &["one", "two", "three"] {
+      body_of(&store, Method::Put, "/v2/keys/key", &[("value", *value)]);
+    }
+
+    let (_, json) = body_of(&store, Method::Get, "/v2/keys/key", &[("wait", "true"), ("waitIndex", "2")]);
+    assert_eq!(json.find_path(&["node", "value"]).unwrap().as_string(), Some("two"));
+
+    let (status, json) = body_of(&store, Method::Get, "/v2/keys/key", &[("wait", "true"), ("waitIndex", "1")]);
+    assert_eq!(status, StatusCode::BadRequest);
+    assert_eq!(json.find("errorCode").unwrap().as_u64(), Some(401));
+  }
+
+  #[test]
+  fn store_stats_test() {
+    let store = MockStore::new(DEFAULT_HISTORY);
+
+    body_of(&store, Method::Put, "/v2/keys/key", &[("value", "one")]);
+    body_of(&store, Method::Put, "/v2/keys/key", &[("value", "two"), ("prevValue", "wrong")]);
+    body_of(&store, Method::Put, "/v2/keys/key", &[("value", "two"), ("prevExist", "true")]);
+    body_of(&store, Method::Get, "/v2/keys/key", &[]);
+    body_of(&store, Method::Get, "/v2/keys/missing", &[]);
+
+    let response = store.handle_json(&Method::Get, "/v2/stats/store", "");
+    let json = Json::from_str(&response.body).unwrap();
+    assert_eq!(json.find("setsSuccess").unwrap().as_u64(), Some(1));
+    assert_eq!(json.find("compareAndSwapFail").unwrap().as_u64(), Some(1));
+    assert_eq!(json.find("updateSuccess").unwrap().as_u64(), Some(1));
+    assert_eq!(json.find("getsSuccess").unwrap().as_u64(), Some(1));
+    assert_eq!(json.find("getsFail").unwrap().as_u64(), Some(1));
+    assert_eq!(json.find("watchers").unwrap().as_u64(), Some(0));
+  }
+}
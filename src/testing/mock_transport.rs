@@ -0,0 +1,76 @@
+use hyper::error::HttpError;
+use std::io;
+use std::sync::Arc;
+use url;
+use etcd::{EtcdClient, EtcdClientBuilder};
+use etcd::etcd_error::EtcdError;
+use etcd::transport::{RequestBody, Transport, TransportRequest, TransportResponse};
+use testing::mock_store::{MockStore, DEFAULT_HISTORY};
+
+/// the endpoint of the clients, the requests never leave the process
+static MOCK_ENDPOINT: &'static str = "http://mock-etcd:4001";
+
+/// MockTransport answers the requests of an EtcdClient from an in memory etcd, without any sockets.
+///
+/// Clones share the same keys.
+///
+/// ```
+/// use etcd_rs::testing::MockTransport;
+///
+/// let client = MockTransport::new().client();
+///
+/// client.set("foo", "bar", None).unwrap();
+/// assert_eq!(client.get("foo").unwrap().unwrap().value.unwrap(), "bar");
+/// ```
+#[derive(Clone)]
+pub struct MockTransport {
+    store: Arc<MockStore>,
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        return MockTransport::with_history(DEFAULT_HISTORY);
+    }
+
+    /// history is the number of events kept for watches with a waitIndex, older indexes return EventIndexCleared
+    pub fn with_history(history: usize) -> MockTransport {
+        let store = Arc::new(MockStore::new(history));
+        store.set_client_url(MOCK_ENDPOINT);
+
+        return MockTransport{ store: store };
+    }
+
+    /// a client using this transport
+    pub fn client(&self) -> EtcdClient {
+        return EtcdClientBuilder::new().endpoint(MOCK_ENDPOINT)
+                                       .transport(Box::new(self.clone()))
+                                       .build()
+                                       .unwrap();
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, EtcdError> {
+        let path = request.url.serialize_path().unwrap_or("/".to_string());
+
+        let mut params = match request.body {
+            Some(RequestBody::Form(body)) => url::form_urlencoded::parse(body.as_bytes()),
+            Some(RequestBody::Json(body)) => {
+                let result = self.store.handle_json(&request.method, &path, body);
+                return Ok(TransportResponse{ status: result.status, headers: result.headers(), body: result.body });
+            },
+            None => vec![],
+        };
+
+        // form values take precedence over query values, as they do in etcd
+        params.extend(request.url.query_pairs().unwrap_or(vec![]).into_iter());
+
+        let cancel = request.cancel;
+        let result = match self.store.handle_until(&request.method, &path, &params, &|| cancel.map_or(false, |c| c.is_cancelled())) {
+            Some(result) => result,
+            None => return Err(EtcdError::from(HttpError::HttpIoError(io::Error::new(io::ErrorKind::ConnectionAborted, "cancelled")))),
+        };
+
+        return Ok(TransportResponse{ status: result.status, headers: result.headers(), body: result.body });
+    }
+}
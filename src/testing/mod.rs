@@ -0,0 +1,9 @@
+//! Support for testing against etcd without running an etcd cluster.
+
+mod mock_cluster;
+mod mock_etcd;
+mod mock_store;
+mod mock_transport;
+
+pub use self::mock_etcd::MockEtcd;
+pub use self::mock_transport::MockTransport;